@@ -0,0 +1,320 @@
+use crate::{
+    domain::{Asset, Release, Repository},
+    github_client::DownloadedAsset,
+};
+use itertools::Itertools;
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Directory inside the data path keeping the downloaded assets
+const CACHE_DIR: &str = "cache";
+/// File describing the asset of a cache entry
+const ENTRY_FILE: &str = "entry.toml";
+/// Directory inside the data path keeping the metadata of the fetched releases
+const RELEASES_DIR: &str = "releases";
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum CacheError {
+    #[error("Failed to read {path:?}.")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to write to {path:?}.")]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to remove {path:?}.")]
+    Remove {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to create directory {path:?}.")]
+    CreateDir {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to serialize cache entry.")]
+    Serialize(#[from] toml::ser::Error),
+    #[error("Failed to serialize release.")]
+    SerializeRelease(#[from] serde_json::Error),
+}
+
+/// Downloaded assets kept in the data path, keyed by their url and digest, so they
+/// don't need to be downloaded again (eg: reinstalling after a rollback), and the
+/// metadata of the fetched releases, so they can be installed offline
+#[derive(Clone, Debug)]
+pub struct Cache {
+    dir: PathBuf,
+    releases_dir: PathBuf,
+}
+
+/// A verified asset in the [`Cache`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub url: String,
+    pub name: String,
+    pub sha256: String,
+    pub downloaded_at: Timestamp,
+    /// Bytes used on disk
+    #[serde(skip)]
+    pub size: u64,
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+impl CacheEntry {
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(&self.name)
+    }
+}
+
+impl Cache {
+    pub fn new(data_path: impl AsRef<Path>) -> Self {
+        let data_path = data_path.as_ref();
+        Self {
+            dir: data_path.join(CACHE_DIR),
+            releases_dir: data_path.join(RELEASES_DIR),
+        }
+    }
+
+    /// Directory to download `asset` into
+    pub fn entry_dir(&self, asset: &Asset) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(asset.browser_download_url.as_bytes());
+        if let Some(digest) = asset.sha256() {
+            hasher.update(b"\n");
+            hasher.update(digest.as_bytes());
+        }
+        let key = hex::encode(hasher.finalize());
+        self.dir.join(&key[..32])
+    }
+
+    /// Cached copy of `asset`, if any, whose content still matches the sha256 recorded
+    /// when it was added and `expected` when given
+    pub async fn get(&self, asset: &Asset, expected: Option<&str>) -> Option<DownloadedAsset> {
+        let entry = read_entry(&self.entry_dir(asset))?;
+        let matches = |o: &str| o.eq_ignore_ascii_case(&entry.sha256);
+        if entry.url != asset.browser_download_url
+            || entry.name != asset.name
+            || !expected.is_none_or(matches)
+        {
+            return None;
+        }
+        let path = entry.path();
+        let data = tokio::fs::read(&path).await.ok()?;
+        let sha256 = hex::encode(Sha256::digest(&data));
+        (data.len() as u64 == asset.size && sha256 == entry.sha256)
+            .then_some(DownloadedAsset { path, sha256 })
+    }
+
+    /// Marks the `downloaded` asset, which should be in its entry dir, as verified
+    pub async fn insert(
+        &self,
+        asset: &Asset,
+        downloaded: &DownloadedAsset,
+    ) -> Result<(), CacheError> {
+        let entry = CacheEntry {
+            url: asset.browser_download_url.clone(),
+            name: asset.name.clone(),
+            sha256: downloaded.sha256.clone(),
+            downloaded_at: Timestamp::now(),
+            size: 0,
+            dir: PathBuf::new(),
+        };
+        let path = self.entry_dir(asset).join(ENTRY_FILE);
+        let s = toml::to_string_pretty(&entry)?;
+        tokio::fs::write(&path, s)
+            .await
+            .map_err(|source| CacheError::Write { path, source })
+    }
+
+    /// Every verified asset, oldest first
+    pub fn list(&self) -> Result<Vec<CacheEntry>, CacheError> {
+        let mut entries = self
+            .dirs()?
+            .iter()
+            .filter_map(|o| read_entry(o))
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|o| o.downloaded_at);
+        Ok(entries)
+    }
+
+    /// Removes the assets downloaded before `before` and the incomplete downloads,
+    /// returns the number of removed entries and the bytes freed
+    pub fn clean(&self, before: Timestamp) -> Result<(usize, u64), CacheError> {
+        let mut removed = 0;
+        let mut freed = 0;
+        for dir in self.dirs()? {
+            let expired = read_entry(&dir).is_none_or(|o| o.downloaded_at < before);
+            if expired {
+                freed += dir_size(&dir);
+                fs::remove_dir_all(&dir).map_err(|source| CacheError::Remove {
+                    path: dir.clone(),
+                    source,
+                })?;
+                removed += 1;
+            }
+        }
+        Ok((removed, freed))
+    }
+
+    /// Saves the metadata of `release`
+    pub async fn insert_release(
+        &self,
+        repo: &Repository,
+        release: &Release,
+    ) -> Result<(), CacheError> {
+        let dir = self.releases_dir.join(&repo.user).join(&repo.repository);
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .map_err(|source| CacheError::CreateDir {
+                path: dir.clone(),
+                source,
+            })?;
+        let path = dir.join(format!("{}.json", release.tag_name.replace('/', "_")));
+        let s = serde_json::to_string_pretty(release)?;
+        tokio::fs::write(&path, s)
+            .await
+            .map_err(|source| CacheError::Write { path, source })
+    }
+
+    /// Releases of `repo` saved with [`Cache::insert_release`], newest first
+    pub fn releases(&self, repo: &Repository) -> Vec<Release> {
+        let dir = self.releases_dir.join(&repo.user).join(&repo.repository);
+        fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|o| fs::read_to_string(o.ok()?.path()).ok())
+            .filter_map(|o| serde_json::from_str::<Release>(&o).ok())
+            .sorted_by(|a, b| b.created_at.cmp(&a.created_at))
+            .collect()
+    }
+
+    fn dirs(&self) -> Result<Vec<PathBuf>, CacheError> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let read_error = |source| CacheError::Read {
+            path: self.dir.clone(),
+            source,
+        };
+        let mut dirs = Vec::new();
+        for entry in fs::read_dir(&self.dir).map_err(read_error)? {
+            let path = entry.map_err(read_error)?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            }
+        }
+        Ok(dirs)
+    }
+}
+
+/// Entry of a cache dir, missing for incomplete or unverified downloads
+fn read_entry(dir: &Path) -> Option<CacheEntry> {
+    let s = fs::read_to_string(dir.join(ENTRY_FILE)).ok()?;
+    let entry = toml::from_str::<CacheEntry>(&s).ok()?;
+    Some(CacheEntry {
+        size: dir_size(dir),
+        dir: dir.to_owned(),
+        ..entry
+    })
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|o| o.ok()?.metadata().ok())
+        .map(|o| o.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::ToolVersion,
+        install::{activate_version, install_version, versions_dir},
+    };
+    use googletest::prelude::*;
+    use std::str::FromStr;
+    use tempfile::tempdir;
+
+    #[gtest]
+    #[tokio::test]
+    async fn cache_works() {
+        let data_path = tempdir().unwrap();
+        let cache = Cache::new(&data_path);
+        let content = b"binary";
+        let sha256 = hex::encode(Sha256::digest(content));
+        let asset = Asset {
+            name: "tool.tar.gz".to_string(),
+            browser_download_url: "https://github.com/user/tool/tool.tar.gz".to_string(),
+            size: content.len() as u64,
+            digest: None,
+        };
+        let dir = cache.entry_dir(&asset);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(&asset.name);
+        fs::write(&path, content).unwrap();
+        // Not verified yet
+        expect_that!(cache.get(&asset, None).await, none());
+
+        let downloaded = DownloadedAsset {
+            path,
+            sha256: sha256.clone(),
+        };
+        cache.insert(&asset, &downloaded).await.unwrap();
+
+        expect_that!(
+            cache.get(&asset, Some(&sha256)).await,
+            some(field!(DownloadedAsset.sha256, eq(&sha256)))
+        );
+        expect_that!(cache.get(&asset, Some(&"0".repeat(64))).await, none());
+        expect_that!(
+            cache.list().unwrap(),
+            elements_are![pat!(CacheEntry {
+                name: eq("tool.tar.gz"),
+                size: gt(&(content.len() as u64)),
+                ..
+            })]
+        );
+        // Corrupted files are downloaded again
+        fs::write(&downloaded.path, b"binarx").unwrap();
+        expect_that!(cache.get(&asset, None).await, none());
+
+        fs::create_dir_all(data_path.path().join("cache/incomplete")).unwrap();
+        let (removed, _) = cache.clean(Timestamp::UNIX_EPOCH).unwrap();
+        expect_that!(removed, eq(1));
+        let (removed, _) = cache.clean(Timestamp::now()).unwrap();
+        expect_that!(removed, eq(1));
+        expect_that!(cache.list().unwrap(), is_empty());
+    }
+
+    #[gtest]
+    fn clean_keeps_installed_versions() {
+        let data_path = tempdir().unwrap();
+        let bin_dir = tempdir().unwrap();
+        let repo = Repository::from_str("someone/cache").unwrap();
+        let versions = versions_dir(&data_path, &repo);
+        let version = ToolVersion::from_str("1.2.3").unwrap();
+        fs::create_dir_all(&versions).unwrap();
+        let staged = versions.join("cache");
+        fs::write(&staged, "binary").unwrap();
+        let path = install_version(&staged, &versions, &version).unwrap();
+        activate_version(&versions, &version, bin_dir.path().join("cache")).unwrap();
+
+        let (removed, _) = Cache::new(&data_path).clean(Timestamp::now()).unwrap();
+
+        expect_that!(removed, eq(0));
+        expect_true!(path.exists());
+    }
+}
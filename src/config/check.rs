@@ -0,0 +1,385 @@
+use super::{RepoConfig, Settings, expand_home};
+use crate::domain::Repository;
+use itertools::Itertools;
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use toml_edit::{Document, DocumentMut, Item, Table};
+
+/// Position in a config file, lines and columns start at 1
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Location {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A problem found by [`check_config`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub location: Location,
+    pub message: String,
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file.display(), self.line, self.column)
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+/// Validates every entry of the config file at `path` and its includes, collecting
+/// all the problems found instead of stopping at the first one
+pub fn check_config(path: &Path) -> Vec<Diagnostic> {
+    let mut checker = Checker::default();
+    checker.check_file(path, None);
+    checker.check_conflicts();
+    let mut diagnostics = checker.diagnostics;
+    diagnostics.sort_by(|a, b| a.location.cmp(&b.location));
+    diagnostics
+}
+
+#[derive(Default)]
+struct Checker {
+    diagnostics: Vec<Diagnostic>,
+    visited: HashSet<PathBuf>,
+    /// Valid repos with the location of their `repo` key
+    repos: Vec<(Repository, Location)>,
+}
+
+/// A config file being checked
+struct File<'a> {
+    path: &'a Path,
+    raw: &'a str,
+}
+
+impl File<'_> {
+    fn location(&self, span: Option<Range<usize>>) -> Location {
+        let offset = span.map(|o| o.start).unwrap_or(0).min(self.raw.len());
+        let before = &self.raw[..offset];
+        let line_start = before.rfind('\n').map(|o| o + 1).unwrap_or(0);
+        Location {
+            file: self.path.to_owned(),
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+
+    /// Location of `key` in `table`, or of the table itself if the key is missing
+    fn key_location(&self, table: &Table, key: &str) -> Location {
+        let span = table.get(key).and_then(Item::span).or_else(|| table.span());
+        self.location(span)
+    }
+}
+
+impl Checker {
+    fn error(&mut self, location: Location, message: impl Into<String>) {
+        self.diagnostics.push(Diagnostic {
+            location,
+            message: message.into(),
+        });
+    }
+
+    /// `from` is the location of the include pointing to this file
+    fn check_file(&mut self, path: &Path, from: Option<Location>) {
+        let from = from.unwrap_or(Location {
+            file: path.to_owned(),
+            line: 1,
+            column: 1,
+        });
+        let canonical = match path.canonicalize() {
+            Ok(o) => o,
+            Err(e) => return self.error(from, format!("Failed to read {path:?}: {e}.")),
+        };
+        if !self.visited.insert(canonical) {
+            return self.error(from, format!("Config {path:?} is included more than once."));
+        }
+        let raw = match std::fs::read_to_string(path) {
+            Ok(o) => o,
+            Err(e) => return self.error(from, format!("Failed to read {path:?}: {e}.")),
+        };
+        let file = File { path, raw: &raw };
+        let doc = match Document::parse(raw.as_str()) {
+            Ok(o) => o,
+            Err(e) => return self.error(file.location(e.span()), e.message().trim()),
+        };
+        let root = doc.as_table();
+        for (key, _) in root.iter() {
+            if !["include", "settings", "repos"].contains(&key) {
+                let span = root.key(key).and_then(|o| o.span());
+                self.error(file.location(span), format!("Unknown key {key:?}."));
+            }
+        }
+        if let Some(item) = root.get("settings") {
+            self.check_settings(&file, item);
+        }
+        match root.get("repos") {
+            Some(item) => self.check_repos(&file, item),
+            None => self.error(file.location(None), "Missing `repos` key."),
+        }
+        if let Some(item) = root.get("include") {
+            let includes = item
+                .as_array()
+                .map(|o| o.iter().map(|o| (o.as_str(), o.span())));
+            let Some(includes) = includes else {
+                return self.error(file.location(item.span()), "`include` should be an array.");
+            };
+            let dir = path.parent().unwrap_or(Path::new(""));
+            for (include, span) in includes.collect::<Vec<_>>() {
+                let location = file.location(span);
+                let Some(include) = include else {
+                    self.error(location, "Included configs should be paths.");
+                    continue;
+                };
+                match expand_home(Path::new(include)) {
+                    Ok(include) => self.check_file(&dir.join(include), Some(location)),
+                    Err(e) => self.error(location, e.to_string()),
+                }
+            }
+        }
+    }
+
+    fn check_settings(&mut self, file: &File, item: &Item) {
+        let Some(table) = item.as_table() else {
+            return self.error(file.location(item.span()), "`settings` should be a table.");
+        };
+        let settings = match toml::from_str::<Settings>(&table_to_string(table)) {
+            Ok(o) => o,
+            Err(e) => return self.error(file.location(table.span()), e.message()),
+        };
+        if settings.jobs == 0 {
+            self.error(
+                file.key_location(table, "jobs"),
+                "`jobs` should be at least 1.",
+            );
+        }
+        for (key, dir) in [
+            ("binaries_dir", &settings.binaries_dir),
+            ("download_dir", &settings.download_dir),
+        ] {
+            let dir = dir.as_deref().map(expand_home).transpose();
+            if let Ok(Some(dir)) = dir
+                && dir.exists()
+                && !dir.is_dir()
+            {
+                self.error(
+                    file.key_location(table, key),
+                    format!("{dir:?} is not a directory."),
+                );
+            }
+        }
+        let ca_bundle = settings.ca_bundle.as_deref().map(expand_home).transpose();
+        if let Ok(Some(path)) = ca_bundle
+            && !path.is_file()
+        {
+            self.error(
+                file.key_location(table, "ca_bundle"),
+                format!("{path:?} is not a file."),
+            );
+        }
+        if let Some(proxy) = &settings.proxy
+            && let Err(e) = reqwest::Url::parse(proxy)
+        {
+            self.error(
+                file.key_location(table, "proxy"),
+                format!("Invalid proxy url {proxy:?}: {e}."),
+            );
+        }
+    }
+
+    fn check_repos(&mut self, file: &File, item: &Item) {
+        let tables = match item {
+            Item::ArrayOfTables(o) => o.iter().collect::<Vec<_>>(),
+            Item::Value(o) if o.as_array().is_some_and(|o| o.is_empty()) => Vec::new(),
+            _ => {
+                let location = file.location(item.span());
+                return self.error(location, "`repos` should be an array of tables.");
+            }
+        };
+        for table in tables {
+            self.check_repo(file, table);
+        }
+    }
+
+    fn check_repo(&mut self, file: &File, table: &Table) {
+        let mut repo_config = match toml::from_str::<RepoConfig>(&table_to_string(table)) {
+            Ok(o) => o,
+            Err(e) => return self.error(file.location(table.span()), e.message()),
+        };
+        let location = file.key_location(table, "repo");
+        match Repository::from_str(&repo_config.repo) {
+            Ok(repo) => self.repos.push((repo, location)),
+            Err(e) => self.error(location, e.to_string()),
+        }
+        let pat = &repo_config.pat;
+        if pat.is_empty() {
+            self.error(
+                file.key_location(table, "pat"),
+                "`pat` should not be empty.",
+            );
+        } else if pat.chars().any(char::is_uppercase) {
+            self.error(
+                file.key_location(table, "pat"),
+                format!(
+                    "`pat` is matched against lowercase asset names, use {pat:?} in lowercase."
+                ),
+            );
+        }
+        if let Err(e) = repo_config.version_probe() {
+            self.error(file.key_location(table, "version_regex"), format!("{e:#}"));
+        }
+        if repo_config.version_timeout_secs == Some(0) {
+            self.error(
+                file.key_location(table, "version_timeout_secs"),
+                "`version_timeout_secs` should be at least 1.",
+            );
+        }
+        let dir = file.path.parent().unwrap_or(Path::new(""));
+        if let Err(e) = repo_config.resolve_paths(dir) {
+            self.error(file.key_location(table, "signature"), e.to_string());
+        } else if let Some(signature) = &repo_config.signature
+            && let Err(e) = signature.public_key()
+        {
+            self.error(file.key_location(table, "signature"), e.to_string());
+        }
+    }
+
+    /// Repos defined more than once or installing binaries with the same name
+    fn check_conflicts(&mut self) {
+        let mut errors = Vec::new();
+        let by_binary = self.repos.iter().into_group_map_by(|(o, _)| &o.repository);
+        for group in by_binary.into_values().filter(|o| o.len() > 1) {
+            for (i, (repo, location)) in group.iter().enumerate() {
+                let others = group
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, (o, location))| (o, location));
+                for (other, other_location) in others {
+                    let message = if repo == other {
+                        format!("Repo {repo} is also defined at {other_location}.")
+                    } else {
+                        format!(
+                            "Binary {:?} is also installed by {other} at {other_location}.",
+                            repo.repository
+                        )
+                    };
+                    errors.push((location.clone(), message));
+                }
+            }
+        }
+        for (location, message) in errors {
+            self.error(location, message);
+        }
+    }
+}
+
+fn table_to_string(table: &Table) -> String {
+    DocumentMut::from(table.clone()).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use googletest::prelude::*;
+    use tempfile::tempdir;
+
+    #[gtest]
+    fn check_config_works() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let team = dir.path().join("team.toml");
+        std::fs::write(
+            &path,
+            r#"include = ["team.toml", "missing.toml"]
+
+[settings]
+jobs = 0
+proxy = "proxy.local"
+ca_bundle = "missing.pem"
+
+[[repos]]
+repo = "jesseduffield/lazygit"
+pat = "Linux"
+
+[[repos]]
+repo = "jesseduffield"
+pat = "linux"
+version_regex = "v(\\d+"
+
+[[repos]]
+repo = "sharkdp/fd"
+pat = "linux"
+signature = { type = "cosign", public_key_file = "missing.pub" }
+"#,
+        )
+        .unwrap();
+        std::fs::write(
+            &team,
+            r#"[[repos]]
+repo = "jesseduffield/lazygit"
+pat = "linux"
+
+[[repos]]
+repo = "someone/fd"
+pat = "linux"
+"#,
+        )
+        .unwrap();
+        let diagnostics = check_config(&path)
+            .into_iter()
+            .map(|o| {
+                let file = o
+                    .location
+                    .file
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string();
+                (file, o.location.line, o.location.column)
+            })
+            .collect::<Vec<_>>();
+        let at = |file: &str, line, column| (file.to_string(), line, column);
+        expect_that!(
+            diagnostics,
+            unordered_elements_are![
+                eq(&at("config.toml", 4, 8)),
+                eq(&at("config.toml", 5, 9)),
+                eq(&at("config.toml", 6, 13)),
+                eq(&at("config.toml", 10, 7)),
+                eq(&at("config.toml", 13, 8)),
+                eq(&at("config.toml", 15, 17)),
+                eq(&at("config.toml", 1, 25)),
+                eq(&at("config.toml", 9, 8)),
+                eq(&at("config.toml", 18, 8)),
+                eq(&at("config.toml", 20, 13)),
+                eq(&at("team.toml", 2, 8)),
+                eq(&at("team.toml", 6, 8)),
+            ]
+        );
+    }
+
+    #[gtest]
+    fn check_config_reports_syntax_errors() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "repos = []\n[settings\n").unwrap();
+        let diagnostics = check_config(&path);
+        expect_that!(
+            diagnostics,
+            elements_are![field!(
+                Diagnostic.location,
+                pat!(Location { line: eq(&2), .. })
+            )]
+        );
+    }
+}
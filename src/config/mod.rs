@@ -0,0 +1,974 @@
+mod check;
+
+pub use check::*;
+
+use crate::{
+    domain::{ChecksumPolicy, Repository, RepositoryError, SignatureConfig, VersionScheme},
+    github_client::ClientOptions,
+    install::InstallLayout,
+    utils::VersionProbe,
+};
+use config::{Config, Environment, FileFormat, Map, Source};
+use directories::BaseDirs;
+use itertools::Itertools;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    str::FromStr,
+    time::Duration,
+};
+use tokio::{
+    fs::{create_dir, read_to_string, write},
+    process::Command,
+};
+use toml_edit::{Array, ArrayOfTables, DocumentMut, Item, Table, Value};
+
+/// Prefix of the environment variables overriding the settings (eg: `DL_RELEASES_JOBS`)
+const ENV_PREFIX: &str = "DL_RELEASES";
+/// Keys of the settings table that can be set from environment variables
+const SETTINGS_KEYS: [&str; 12] = [
+    "binaries_dir",
+    "download_dir",
+    "jobs",
+    "token_command",
+    "api_url",
+    "keep_downloads",
+    "checksum",
+    "proxy",
+    "ca_bundle",
+    "system_certs",
+    "connect_timeout_secs",
+    "read_timeout_secs",
+];
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ConfigError {
+    #[error("No valid home directory path found.")]
+    NoHomeDir,
+    #[error("No executable dir found.")]
+    NoExecutableDir,
+    #[error("Config file {0:?} doesn't exist.")]
+    NotFound(PathBuf),
+    #[error("Failed to read {path:?}.")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to write to {path:?}.")]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to create directory {path:?}.")]
+    CreateDir {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to load configuration {path:?}.")]
+    Load {
+        path: PathBuf,
+        source: Box<config::ConfigError>,
+    },
+    #[error("Failed to parse {path:?}.")]
+    Parse {
+        path: PathBuf,
+        source: Box<toml_edit::TomlError>,
+    },
+    #[error("Failed to serialize config.")]
+    Serialize(#[from] toml::ser::Error),
+    #[error("Config {0:?} is included more than once.")]
+    IncludedTwice(PathBuf),
+    #[error("Found duplicated repos on config: {0}.")]
+    DuplicatedRepos(String),
+    #[error("`settings.jobs` should be at least 1.")]
+    ZeroJobs,
+    #[error("Invalid version_regex for {repo:?}: {error}")]
+    InvalidVersionRegex { repo: String, error: regex::Error },
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+    #[error("Repo {0:?} not found on config.")]
+    RepoNotFound(String),
+    #[error("`repos` should be an array of tables.")]
+    InvalidRepos,
+    #[error("Failed to execute token_command: {command:?}.")]
+    TokenCommand {
+        command: String,
+        source: std::io::Error,
+    },
+    #[error("token_command {command:?} failed ({status}).")]
+    TokenCommandFailed { command: String, status: ExitStatus },
+    #[error("Invalid token.")]
+    InvalidToken,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Configuration {
+    /// Other config files to merge repos from, relative paths are resolved from the
+    /// directory of the including file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<PathBuf>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub settings: Settings,
+    pub repos: Vec<RepoConfig>,
+}
+
+/// Global settings from the `[settings]` table. Precedence is CLI > `DL_RELEASES_*`
+/// environment variables > config file > defaults.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Final binaries location (default: ~/.local/bin/)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub binaries_dir: Option<PathBuf>,
+    /// Directory to download assets into, it also keeps the manifest, backups and
+    /// versions (default: the data directory)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_dir: Option<PathBuf>,
+    /// Maximum number of repositories to update concurrently
+    pub jobs: u16,
+    /// Shell command printing the token used to authenticate to the API (eg: "gh auth token")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_command: Option<String>,
+    /// Base URL of the GitHub API
+    pub api_url: String,
+    /// Keep downloaded assets after extracting them
+    pub keep_downloads: bool,
+    /// Checksum policy of repositories without one
+    pub checksum: ChecksumPolicy,
+    /// Proxy used for every request (default: the `HTTP_PROXY`, `HTTPS_PROXY` and
+    /// `ALL_PROXY` environment variables)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// PEM file with root certificates to trust besides the bundled ones (eg: a
+    /// corporate CA)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_bundle: Option<PathBuf>,
+    /// Trust the certificates of the system store besides the bundled ones, eg: for
+    /// TLS-inspecting proxies
+    pub system_certs: bool,
+    /// Seconds to wait for a connection, 0 disables the timeout
+    pub connect_timeout_secs: u64,
+    /// Seconds to wait for data from the server, 0 disables the timeout
+    pub read_timeout_secs: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            binaries_dir: None,
+            download_dir: None,
+            jobs: 4,
+            token_command: None,
+            api_url: "https://api.github.com".to_string(),
+            keep_downloads: true,
+            checksum: ChecksumPolicy::default(),
+            proxy: None,
+            ca_bundle: None,
+            system_certs: true,
+            connect_timeout_secs: 30,
+            read_timeout_secs: 60,
+        }
+    }
+}
+
+/// Settings given as CLI flags
+#[derive(Clone, Debug, Default)]
+pub struct SettingsOverrides {
+    pub binaries_dir: Option<PathBuf>,
+    pub download_dir: Option<PathBuf>,
+    pub jobs: Option<u16>,
+}
+
+impl Settings {
+    /// Runs `token_command` to get the API token
+    pub async fn token(&self) -> Result<Option<String>, ConfigError> {
+        let Some(command) = &self.token_command else {
+            return Ok(None);
+        };
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .output()
+            .await
+            .map_err(|source| ConfigError::TokenCommand {
+                command: command.clone(),
+                source,
+            })?;
+        if !output.status.success() {
+            return Err(ConfigError::TokenCommandFailed {
+                command: command.clone(),
+                status: output.status,
+            });
+        }
+        let token = String::from_utf8(output.stdout).map_err(|_| ConfigError::InvalidToken)?;
+        Ok(Some(token.trim().to_string()))
+    }
+
+    /// Network options of the client, `NO_PROXY` applies to the `proxy` setting too
+    pub fn client_options(&self) -> Result<ClientOptions, ConfigError> {
+        let env = |name: &str| {
+            std::env::var(name)
+                .or_else(|_| std::env::var(name.to_lowercase()))
+                .ok()
+                .filter(|o| !o.is_empty())
+        };
+        let timeout = |secs| (secs > 0).then(|| Duration::from_secs(secs));
+        Ok(ClientOptions {
+            proxy: self.proxy.clone(),
+            no_proxy: env("NO_PROXY"),
+            ca_bundle: self.ca_bundle.as_deref().map(expand_home).transpose()?,
+            system_certs: self.system_certs,
+            connect_timeout: timeout(self.connect_timeout_secs),
+            read_timeout: timeout(self.read_timeout_secs),
+        })
+    }
+}
+
+/// Maps the `DL_RELEASES_*` environment variables to keys of the settings table
+#[derive(Clone, Debug)]
+struct SettingsEnv(Environment);
+
+impl Source for SettingsEnv {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<Map<String, config::Value>, config::ConfigError> {
+        let settings = self
+            .0
+            .collect()?
+            .into_iter()
+            .filter(|(k, _)| SETTINGS_KEYS.contains(&k.as_str()))
+            .map(|(k, v)| (format!("settings.{k}"), v))
+            .collect();
+        Ok(settings)
+    }
+}
+
+impl Configuration {
+    pub fn read_repositories(self) -> Result<Vec<(Repository, RepoConfig)>, ConfigError> {
+        let repos = self
+            .repos
+            .into_iter()
+            .map(|o| Repository::from_str(&o.repo).map(|repo| (repo, o)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(repos)
+    }
+
+    pub fn validate(self) -> Result<Self, ConfigError> {
+        let duplicated_repos = self
+            .repos
+            .iter()
+            .into_group_map_by(|o| &o.repo)
+            .into_iter()
+            .filter(|(_, o)| o.len() > 1)
+            .sorted_by_key(|(repo, _)| *repo)
+            .map(|(repo, o)| {
+                let files = o
+                    .iter()
+                    .filter_map(|o| o.source.as_ref())
+                    .unique()
+                    .join(", ");
+                format!("{repo} ({files})")
+            })
+            .collect::<Vec<_>>();
+        if !duplicated_repos.is_empty() {
+            let s = duplicated_repos.into_iter().join(", ");
+            return Err(ConfigError::DuplicatedRepos(s));
+        }
+        if self.settings.jobs == 0 {
+            return Err(ConfigError::ZeroJobs);
+        }
+        for repo in &self.repos {
+            repo.version_probe()?;
+        }
+        Ok(self)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RepoConfig {
+    /// Repository name in format user/repo_name
+    pub repo: String,
+    /// Pattern to look in into assets to pick the one to download
+    pub pat: String,
+    /// Checksum verification policy for downloaded assets (default: `settings.checksum`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<ChecksumPolicy>,
+    /// Signature verification settings, assets are not verified if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<SignatureConfig>,
+    /// How binaries are laid out on disk
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub layout: InstallLayout,
+    /// Arguments used to query the binary version (default: ["--version"])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_args: Option<Vec<String>>,
+    /// Regex to find the version on the output of the version command, the first
+    /// capture group is used if present
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_regex: Option<String>,
+    /// Versioning scheme used by the repository
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub version_scheme: VersionScheme,
+    /// Maximum number of seconds the binary can take to report its version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_timeout_secs: Option<u64>,
+    /// Trust the release version instead of executing binaries to get their version,
+    /// the installed version is read from the manifest
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub trust_release_version: bool,
+    /// Shell commands to run before installing a new version, the install is aborted
+    /// if any of them fails
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_install: Vec<String>,
+    /// Shell commands to run after installing a new version
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_install: Vec<String>,
+    /// Restore the previous binary if a `post_install` command fails
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub rollback_on_hook_failure: bool,
+    /// Release tag to install instead of the latest release
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pin: Option<String>,
+    /// Config file this repo was read from
+    #[serde(skip)]
+    pub source: Option<String>,
+}
+
+impl RepoConfig {
+    pub fn new(repo: String, pat: String) -> Self {
+        Self {
+            repo,
+            pat,
+            checksum: None,
+            signature: None,
+            layout: InstallLayout::default(),
+            version_args: None,
+            version_regex: None,
+            version_scheme: VersionScheme::default(),
+            version_timeout_secs: None,
+            trust_release_version: false,
+            pre_install: Vec::new(),
+            post_install: Vec::new(),
+            rollback_on_hook_failure: false,
+            pin: None,
+            source: None,
+        }
+    }
+
+    pub fn version_probe(&self) -> Result<VersionProbe, ConfigError> {
+        let mut probe = VersionProbe {
+            scheme: self.version_scheme,
+            ..Default::default()
+        };
+        if let Some(secs) = self.version_timeout_secs {
+            probe.timeout = Duration::from_secs(secs);
+        }
+        if let Some(args) = &self.version_args {
+            probe.args.clone_from(args);
+        }
+        if let Some(re) = &self.version_regex {
+            let re = Regex::new(re).map_err(|error| ConfigError::InvalidVersionRegex {
+                repo: self.repo.clone(),
+                error,
+            })?;
+            probe.regex = Some(re);
+        }
+        Ok(probe)
+    }
+
+    /// Resolves the paths of the repo like includes, relative to `dir`, the directory
+    /// of its config file
+    fn resolve_paths(&mut self, dir: &Path) -> Result<(), ConfigError> {
+        if let Some(
+            SignatureConfig::Minisign {
+                public_key_file: Some(path),
+                ..
+            }
+            | SignatureConfig::Cosign {
+                public_key_file: Some(path),
+                ..
+            },
+        ) = &mut self.signature
+        {
+            *path = dir.join(expand_home(path)?);
+        }
+        Ok(())
+    }
+}
+
+fn is_default<T: Default + PartialEq>(x: &T) -> bool {
+    x == &T::default()
+}
+
+/// Path of the config file, `path` is used instead of the default location if given
+pub async fn get_config_path(path: Option<PathBuf>) -> Result<PathBuf, ConfigError> {
+    if let Some(path) = path {
+        let path = expand_home(&path)?;
+        if !path.is_file() {
+            return Err(ConfigError::NotFound(path));
+        }
+        return Ok(path);
+    }
+    let base_dirs = BaseDirs::new().ok_or(ConfigError::NoHomeDir)?;
+    let parent = base_dirs.config_dir().join("dl-releases");
+    if !parent.exists() {
+        create_dir(&parent)
+            .await
+            .map_err(|source| ConfigError::CreateDir {
+                path: parent.clone(),
+                source,
+            })?;
+    }
+    let path = parent.join("config.toml");
+    if !path.exists() {
+        let config = Configuration::default();
+        let s = toml::to_string_pretty(&config)?;
+        if let Err(source) = write(&path, s).await {
+            return Err(ConfigError::Write { path, source });
+        }
+    }
+    Ok(path)
+}
+
+pub async fn get_data_path() -> Result<PathBuf, ConfigError> {
+    let base_dirs = BaseDirs::new().ok_or(ConfigError::NoHomeDir)?;
+    let path = base_dirs.data_dir().join("dl-releases");
+    if !path.exists()
+        && let Err(source) = create_dir(&path).await
+    {
+        return Err(ConfigError::CreateDir { path, source });
+    }
+    Ok(path)
+}
+
+pub fn get_binaries_path() -> Result<PathBuf, ConfigError> {
+    let base_dirs = BaseDirs::new().ok_or(ConfigError::NoHomeDir)?;
+    let path = base_dirs
+        .executable_dir()
+        .ok_or(ConfigError::NoExecutableDir)?
+        .to_owned();
+    Ok(path)
+}
+
+pub fn get_configuration(path: &Path) -> Result<Configuration, ConfigError> {
+    get_configuration_with(path, &SettingsOverrides::default())
+}
+
+/// Reads the configuration applying environment variables and CLI flags to its settings
+pub fn get_configuration_with(
+    path: &Path,
+    overrides: &SettingsOverrides,
+) -> Result<Configuration, ConfigError> {
+    build_configuration(path, settings_env(), overrides)?.validate()
+}
+
+/// Reads only the settings, so commands fixing the repos don't require them to be valid
+pub fn get_settings(path: &Path, overrides: &SettingsOverrides) -> Result<Settings, ConfigError> {
+    Ok(build_configuration(path, settings_env(), overrides)?.settings)
+}
+
+fn settings_env() -> Environment {
+    Environment::with_prefix(ENV_PREFIX)
+        .prefix_separator("_")
+        .try_parsing(true)
+}
+
+fn build_configuration(
+    path: &Path,
+    env: Environment,
+    overrides: &SettingsOverrides,
+) -> Result<Configuration, ConfigError> {
+    build_configuration_from(path, config::File::from(path), env, overrides)
+}
+
+/// Reads the configuration from `file`, the contents of the config at `path`
+fn build_configuration_from(
+    path: &Path,
+    file: impl Source + Send + Sync + 'static,
+    env: Environment,
+    overrides: &SettingsOverrides,
+) -> Result<Configuration, ConfigError> {
+    let path_str = |o: &Option<PathBuf>| o.as_ref().map(|o| o.to_string_lossy().into_owned());
+    let load_error = |source| ConfigError::Load {
+        path: path.to_owned(),
+        source: Box::new(source),
+    };
+    let mut config = Config::builder()
+        .add_source(file)
+        .add_source(SettingsEnv(env))
+        .set_override_option("settings.binaries_dir", path_str(&overrides.binaries_dir))
+        .and_then(|o| {
+            o.set_override_option("settings.download_dir", path_str(&overrides.download_dir))
+        })
+        .and_then(|o| o.set_override_option("settings.jobs", overrides.jobs))
+        .and_then(|o| o.build())
+        .and_then(|o| o.try_deserialize::<Configuration>())
+        .map_err(load_error)?;
+    let root = path.canonicalize().map_err(|source| ConfigError::Read {
+        path: path.to_owned(),
+        source,
+    })?;
+    config.merge_includes(path, &mut HashSet::from([root]))?;
+    Ok(config)
+}
+
+impl Configuration {
+    /// Sets the source of the repos in `path` and appends the repos of its included
+    /// files, `visited` keeps the files already read to detect include cycles
+    fn merge_includes(
+        &mut self,
+        path: &Path,
+        visited: &mut HashSet<PathBuf>,
+    ) -> Result<(), ConfigError> {
+        let source = path.to_string_lossy();
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for repo in &mut self.repos {
+            repo.source.get_or_insert_with(|| source.to_string());
+            repo.resolve_paths(dir)?;
+        }
+        for include in &self.include.clone() {
+            let include = dir.join(expand_home(include)?);
+            let canonical = match include.canonicalize() {
+                Ok(o) => o,
+                Err(source) => {
+                    return Err(ConfigError::Read {
+                        path: include,
+                        source,
+                    });
+                }
+            };
+            if !visited.insert(canonical) {
+                return Err(ConfigError::IncludedTwice(include));
+            }
+            let mut other = Config::builder()
+                .add_source(config::File::from(include.as_path()))
+                .build()
+                .and_then(|o| o.try_deserialize::<Configuration>())
+                .map_err(|source| ConfigError::Load {
+                    path: include.clone(),
+                    source: Box::new(source),
+                })?;
+            other.merge_includes(&include, visited)?;
+            self.repos.extend(other.repos);
+        }
+        Ok(())
+    }
+}
+
+/// Replaces a leading `~` with the home directory
+fn expand_home(path: &Path) -> Result<PathBuf, ConfigError> {
+    match path.strip_prefix("~") {
+        Ok(rest) => {
+            let base_dirs = BaseDirs::new().ok_or(ConfigError::NoHomeDir)?;
+            Ok(base_dirs.home_dir().join(rest))
+        }
+        Err(_) => Ok(path.to_owned()),
+    }
+}
+
+/// Edits the config file in place, preserving comments and formatting
+pub struct ConfigEditor {
+    path: PathBuf,
+    doc: DocumentMut,
+}
+
+impl ConfigEditor {
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref().to_owned();
+        let s = match read_to_string(&path).await {
+            Ok(s) => s,
+            Err(source) => return Err(ConfigError::Read { path, source }),
+        };
+        match s.parse::<DocumentMut>() {
+            Ok(doc) => Ok(Self { path, doc }),
+            Err(source) => Err(ConfigError::Parse {
+                path,
+                source: Box::new(source),
+            }),
+        }
+    }
+
+    /// Opens the config file defining `repo`, either the one at `path` or one of its
+    /// includes
+    pub async fn open_for_repo(path: impl AsRef<Path>, repo: &str) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let overrides = SettingsOverrides::default();
+        let config = build_configuration(path, settings_env(), &overrides)?;
+        let source = config
+            .repos
+            .into_iter()
+            .find(|o| o.repo == repo)
+            .and_then(|o| o.source)
+            .ok_or_else(|| ConfigError::RepoNotFound(repo.to_string()))?;
+        Self::open(source).await
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the edited config, the file is left untouched if it's not valid
+    pub async fn save(&self) -> Result<(), ConfigError> {
+        let s = self.doc.to_string();
+        let file = config::File::from_str(&s, FileFormat::Toml);
+        let overrides = SettingsOverrides::default();
+        build_configuration_from(&self.path, file, settings_env(), &overrides)?.validate()?;
+        write(&self.path, s)
+            .await
+            .map_err(|source| ConfigError::Write {
+                path: self.path.clone(),
+                source,
+            })
+    }
+
+    fn repos_mut(&mut self) -> Result<&mut ArrayOfTables, ConfigError> {
+        // Inline arrays become arrays of tables, whose headers shouldn't keep the
+        // spacing around the key (eg: `[[repos ]]`)
+        if self.doc.get("repos").is_some_and(Item::is_array)
+            && let Some(mut key) = self.doc.key_mut("repos")
+        {
+            key.leaf_decor_mut().clear();
+        }
+        let item = self
+            .doc
+            .entry("repos")
+            .or_insert_with(|| Item::ArrayOfTables(ArrayOfTables::new()));
+        // The default config has an inline `repos = []`
+        if item.as_array().is_some_and(|o| o.is_empty()) {
+            *item = Item::ArrayOfTables(ArrayOfTables::new());
+        } else if item.is_array() {
+            let array = std::mem::take(item);
+            *item = array
+                .into_array_of_tables()
+                .map_err(|_| ConfigError::InvalidRepos)?
+                .into();
+        }
+        item.as_array_of_tables_mut()
+            .ok_or(ConfigError::InvalidRepos)
+    }
+
+    fn repo_mut(&mut self, repo: &str) -> Result<&mut Table, ConfigError> {
+        self.repos_mut()?
+            .iter_mut()
+            .find(|o| o.get("repo").and_then(|o| o.as_str()) == Some(repo))
+            .ok_or_else(|| ConfigError::RepoNotFound(repo.to_string()))
+    }
+
+    pub fn add_repo(&mut self, repo_config: &RepoConfig) -> Result<(), ConfigError> {
+        let s = toml::to_string(repo_config)?;
+        let table = s
+            .parse::<DocumentMut>()
+            .map_err(|source| ConfigError::Parse {
+                path: self.path.clone(),
+                source: Box::new(source),
+            })?
+            .as_table()
+            .clone();
+        self.repos_mut()?.push(table);
+        Ok(())
+    }
+
+    pub fn remove_repo(&mut self, repo: &str) -> Result<(), ConfigError> {
+        let repos = self.repos_mut()?;
+        let n = repos.len();
+        repos.retain(|o| o.get("repo").and_then(|o| o.as_str()) != Some(repo));
+        if repos.len() == n {
+            return Err(ConfigError::RepoNotFound(repo.to_string()));
+        }
+        // Empty arrays of tables aren't written, but `repos` is required
+        if repos.is_empty() {
+            self.doc.remove("repos");
+            self.doc
+                .insert("repos", Item::Value(Value::Array(Array::new())));
+        }
+        Ok(())
+    }
+
+    pub fn set_pattern(&mut self, repo: &str, pat: &str) -> Result<(), ConfigError> {
+        set_value(self.repo_mut(repo)?, "pat", pat);
+        Ok(())
+    }
+
+    /// Pins `repo` to a release tag, or unpins it if `tag` is missing
+    pub fn pin(&mut self, repo: &str, tag: Option<&str>) -> Result<(), ConfigError> {
+        let table = self.repo_mut(repo)?;
+        match tag {
+            Some(tag) => set_value(table, "pin", tag),
+            None => {
+                table.remove("pin");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sets `key` keeping the comments around the previous value
+fn set_value(table: &mut Table, key: &str, v: impl Into<Value>) {
+    let mut v = v.into();
+    if let Some(old) = table.get(key).and_then(|o| o.as_value()) {
+        *v.decor_mut() = old.decor().clone();
+    }
+    table[key] = Item::Value(v);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use googletest::prelude::*;
+    use tempfile::tempdir;
+
+    const CONFIG: &str = r#"# My tools
+
+# Git
+[[repos]]
+repo = "jesseduffield/lazygit"
+pat = "linux_x86_64" # the tar.gz one
+
+# Docker
+[[repos]]
+repo = "jesseduffield/lazydocker"
+pat = "linux_x86_64"
+"#;
+
+    #[gtest]
+    #[tokio::test]
+    async fn config_editor_preserves_comments() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        write(&path, CONFIG).await.unwrap();
+        let mut editor = ConfigEditor::open(&path).await.unwrap();
+        editor
+            .set_pattern("jesseduffield/lazygit", "x86_64")
+            .unwrap();
+        editor
+            .pin("jesseduffield/lazygit", Some("v0.54.1"))
+            .unwrap();
+        editor.remove_repo("jesseduffield/lazydocker").unwrap();
+        let repo_config = RepoConfig::new("rust-lang/rust-analyzer".into(), "gnu".into());
+        editor.add_repo(&repo_config).unwrap();
+        editor.save().await.unwrap();
+        let s = read_to_string(&path).await.unwrap();
+        expect_that!(
+            s,
+            eq(r#"# My tools
+
+# Git
+[[repos]]
+repo = "jesseduffield/lazygit"
+pat = "x86_64" # the tar.gz one
+pin = "v0.54.1"
+
+[[repos]]
+repo = "rust-lang/rust-analyzer"
+pat = "gnu"
+"#)
+        );
+        let config = get_configuration(&path).unwrap();
+        expect_that!(config.repos, len(eq(2)));
+        expect_that!(
+            editor.remove_repo("jesseduffield/lazydocker"),
+            err(anything())
+        );
+    }
+
+    #[gtest]
+    #[tokio::test]
+    async fn config_editor_keeps_invalid_edits_unsaved() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        write(&path, CONFIG).await.unwrap();
+        let mut editor = ConfigEditor::open(&path).await.unwrap();
+        let repo_config = RepoConfig::new("jesseduffield/lazygit".into(), "linux".into());
+        editor.add_repo(&repo_config).unwrap();
+        expect_that!(
+            editor.save().await,
+            err(pat!(ConfigError::DuplicatedRepos(_)))
+        );
+        expect_that!(read_to_string(&path).await.unwrap(), eq(CONFIG));
+    }
+
+    #[gtest]
+    #[tokio::test]
+    async fn config_editor_works_with_default_config() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let s = toml::to_string_pretty(&Configuration::default()).unwrap();
+        write(&path, s).await.unwrap();
+        let mut editor = ConfigEditor::open(&path).await.unwrap();
+        let repo_config = RepoConfig::new("jesseduffield/lazygit".into(), "linux".into());
+        editor.add_repo(&repo_config).unwrap();
+        editor.save().await.unwrap();
+        let config = get_configuration(&path).unwrap();
+        expect_that!(
+            config.repos,
+            elements_are![field!(RepoConfig.repo, eq("jesseduffield/lazygit"))]
+        );
+        let s = read_to_string(&path).await.unwrap();
+        expect_that!(s, contains_substring("[[repos]]"));
+    }
+
+    #[gtest]
+    fn settings_precedence_works() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let s = r#"
+repos = []
+
+[settings]
+jobs = 2
+api_url = "https://github.example.com/api/v3"
+keep_downloads = false
+"#;
+        std::fs::write(&path, s).unwrap();
+        let env = settings_env().source(Some(Map::from([
+            ("DL_RELEASES_JOBS".to_string(), "8".to_string()),
+            ("DL_RELEASES_CHECKSUM".to_string(), "require".to_string()),
+            ("DL_RELEASES_REPO".to_string(), "user/tool".to_string()),
+        ])));
+        let overrides = SettingsOverrides {
+            jobs: Some(16),
+            ..Default::default()
+        };
+        let config = build_configuration(&path, env.clone(), &overrides).unwrap();
+        expect_that!(
+            config.settings,
+            pat!(Settings {
+                binaries_dir: none(),
+                jobs: eq(&16),
+                api_url: eq("https://github.example.com/api/v3"),
+                keep_downloads: eq(&false),
+                checksum: eq(&ChecksumPolicy::Require),
+                ..
+            })
+        );
+        let config = build_configuration(&path, env, &SettingsOverrides::default()).unwrap();
+        expect_that!(config.settings.jobs, eq(8));
+    }
+
+    #[gtest]
+    fn includes_work() {
+        let dir = tempdir().unwrap();
+        let team_dir = dir.path().join("team");
+        std::fs::create_dir(&team_dir).unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            r#"
+include = ["team/config.toml"]
+
+[[repos]]
+repo = "jesseduffield/lazygit"
+pat = "linux_x86_64"
+"#,
+        )
+        .unwrap();
+        std::fs::write(
+            team_dir.join("config.toml"),
+            r#"
+include = ["tools.toml"]
+repos = []
+"#,
+        )
+        .unwrap();
+        let tools = team_dir.join("tools.toml");
+        std::fs::write(
+            &tools,
+            r#"
+[[repos]]
+repo = "jesseduffield/lazydocker"
+pat = "linux_x86_64"
+signature = { type = "minisign", public_key_file = "keys/lazydocker.pub" }
+"#,
+        )
+        .unwrap();
+        let config = get_configuration(&path).unwrap();
+        expect_that!(
+            config.repos,
+            elements_are![
+                field!(RepoConfig.repo, eq("jesseduffield/lazygit")),
+                field!(RepoConfig.repo, eq("jesseduffield/lazydocker")),
+            ]
+        );
+        let source = tools.to_string_lossy().to_string();
+        expect_that!(config.repos[1].source, some(eq(&source)));
+        // Relative to the file the repo is read from
+        expect_that!(
+            config.repos[1].signature,
+            some(eq(&SignatureConfig::Minisign {
+                public_key: None,
+                public_key_file: Some(team_dir.join("keys/lazydocker.pub")),
+            }))
+        );
+        // Duplicated across files
+        std::fs::write(
+            &tools,
+            r#"
+[[repos]]
+repo = "jesseduffield/lazygit"
+pat = "linux_x86_64"
+"#,
+        )
+        .unwrap();
+        expect_that!(
+            get_configuration(&path),
+            err(displays_as(contains_substring(source)))
+        );
+        // Cycles
+        std::fs::write(
+            &tools,
+            r#"include = ["../config.toml"]
+repos = []"#,
+        )
+        .unwrap();
+        expect_that!(
+            get_configuration(&path),
+            err(displays_as(contains_substring("included more than once")))
+        );
+    }
+
+    #[gtest]
+    #[tokio::test]
+    async fn config_editor_edits_included_repos() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let team = dir.path().join("team.toml");
+        let config = "include = [\"team.toml\"]\nrepos = []\n";
+        write(&path, config).await.unwrap();
+        write(&team, CONFIG).await.unwrap();
+
+        let mut editor = ConfigEditor::open_for_repo(&path, "jesseduffield/lazydocker")
+            .await
+            .unwrap();
+        editor
+            .pin("jesseduffield/lazydocker", Some("v0.24.1"))
+            .unwrap();
+        editor.save().await.unwrap();
+
+        expect_that!(editor.path(), eq(&team));
+        expect_that!(read_to_string(&path).await, ok(eq(config)));
+        expect_that!(
+            read_to_string(&team).await,
+            ok(contains_substring("pin = \"v0.24.1\""))
+        );
+        // Included files stay valid without repos
+        for repo in ["jesseduffield/lazygit", "jesseduffield/lazydocker"] {
+            let mut editor = ConfigEditor::open_for_repo(&path, repo).await.unwrap();
+            editor.remove_repo(repo).unwrap();
+            editor.save().await.unwrap();
+        }
+        expect_that!(
+            get_configuration(&path),
+            ok(field!(Configuration.repos, is_empty()))
+        );
+        expect_that!(
+            ConfigEditor::open_for_repo(&path, "sharkdp/fd").await.err(),
+            some(pat!(ConfigError::RepoNotFound(_)))
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use super::{Asset, Release};
+use serde::{Deserialize, Serialize};
+
+/// How to proceed when verifying a downloaded asset against its sha256 checksum
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumPolicy {
+    /// Fail if no checksum is found for the asset
+    Require,
+    /// Warn if no checksum is found for the asset
+    #[default]
+    Warn,
+    /// Skip verification
+    Off,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ChecksumError {
+    #[error("No checksum found for {0:?}.")]
+    Missing(String),
+    #[error("Checksum mismatch for {name:?}: expected {expected}, got {actual}.")]
+    Mismatch {
+        name: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl Asset {
+    /// Sha256 digest reported by the GitHub API (eg: "sha256:abc...")
+    pub fn sha256(&self) -> Option<String> {
+        self.digest
+            .as_deref()
+            .and_then(|o| o.strip_prefix("sha256:"))
+            .filter(|o| is_sha256(o))
+            .map(str::to_lowercase)
+    }
+}
+
+impl Release {
+    /// Find assets that may contain the checksum for `asset`, sidecar files first
+    /// (eg: "tool.tar.gz.sha256") followed by generic ones (eg: "checksums.txt")
+    pub fn find_checksum_assets(&self, asset: &Asset) -> Vec<&Asset> {
+        let name = asset.name.to_lowercase();
+        let sidecars = ["sha256", "sha256sum", "sha256.txt"].map(|o| format!("{name}.{o}"));
+        let (sidecar, generic): (Vec<_>, Vec<_>) = self
+            .assets
+            .iter()
+            .filter_map(|o| {
+                let s = o.name.to_lowercase();
+                if sidecars.contains(&s) {
+                    Some((true, o))
+                } else if is_checksum_file(&s) {
+                    Some((false, o))
+                } else {
+                    None
+                }
+            })
+            .partition(|(is_sidecar, _)| *is_sidecar);
+        sidecar.into_iter().chain(generic).map(|(_, o)| o).collect()
+    }
+}
+
+/// Whether `name` is a checksum file of the whole release (eg: "checksums.txt",
+/// "tool_1.0.0_checksums.txt" or "SHA256SUMS"), and not one of its signatures
+fn is_checksum_file(name: &str) -> bool {
+    let stem = name.strip_suffix(".txt").unwrap_or(name);
+    stem.ends_with("checksums") || stem.ends_with("sha256sums")
+}
+
+/// Looks for the sha256 of `name` in the content of a checksum file.
+///
+/// Supports the GNU format ("<hash>  <name>" or "<hash> *<name>"), the BSD format
+/// ("SHA256 (<name>) = <hash>") and sidecar files containing only the hash.
+pub fn parse_checksum_file(content: &str, name: &str) -> Option<String> {
+    let lines = content
+        .lines()
+        .map(str::trim)
+        .filter(|o| !o.is_empty())
+        .collect::<Vec<_>>();
+    if let [line] = lines.as_slice()
+        && is_sha256(line)
+    {
+        return Some(line.to_lowercase());
+    }
+    lines.into_iter().find_map(|line| {
+        let (hash, fname) = match line.strip_prefix("SHA256 (") {
+            Some(rest) => {
+                let (fname, hash) = rest.split_once(") = ")?;
+                (hash, fname)
+            }
+            None => {
+                let (hash, fname) = line.split_once(char::is_whitespace)?;
+                (hash, fname.trim_start().trim_start_matches('*'))
+            }
+        };
+        let fname = fname.rsplit('/').next().unwrap_or(fname);
+        (fname == name && is_sha256(hash)).then(|| hash.to_lowercase())
+    })
+}
+
+/// Checks `actual` against `expected` following `policy`, returns a warning message
+/// when verification was skipped.
+pub fn verify_checksum(
+    policy: ChecksumPolicy,
+    name: &str,
+    expected: Option<&str>,
+    actual: &str,
+) -> Result<Option<String>, ChecksumError> {
+    match (policy, expected) {
+        (ChecksumPolicy::Off, _) => Ok(None),
+        (ChecksumPolicy::Require, None) => Err(ChecksumError::Missing(name.to_string())),
+        (ChecksumPolicy::Warn, None) => Ok(Some(format!(
+            "No checksum found for {name:?}, skipping verification."
+        ))),
+        (_, Some(expected)) if expected.eq_ignore_ascii_case(actual) => Ok(None),
+        (_, Some(expected)) => Err(ChecksumError::Mismatch {
+            name: name.to_string(),
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+        }),
+    }
+}
+
+fn is_sha256(s: &str) -> bool {
+    s.len() == 64 && s.chars().all(|o| o.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use googletest::prelude::*;
+    use std::fs::read_to_string;
+
+    const HASH: &str = "0f8c1b7c8e8d1c1a4a8f1f0b0c3e7f6d5a4b3c2d1e0f9a8b7c6d5e4f3a2b1c0d";
+
+    #[gtest]
+    fn asset_digest_works() {
+        let s = read_to_string("src/domain/test_files/jesseduffield_lazygit.json").unwrap();
+        let release = serde_json::from_str::<Release>(&s).unwrap();
+        let asset = release.find_asset("linux_x86_64").unwrap();
+        expect_that!(
+            asset.sha256(),
+            some(eq(
+                "ad89b26b479fb89b417e426690d9af1d3b73c4dc7952d32ab7bb55c0e25a86ca"
+            ))
+        );
+    }
+
+    #[gtest]
+    fn find_checksum_assets_works() {
+        let s = read_to_string("src/domain/test_files/jesseduffield_lazygit.json").unwrap();
+        let mut release = serde_json::from_str::<Release>(&s).unwrap();
+        let signatures = [
+            "checksums.txt.sig",
+            "checksums.txt.pem",
+            "SHA256SUMS.minisig",
+        ];
+        for name in ["SHA256SUMS"].into_iter().chain(signatures) {
+            release.assets.push(Asset {
+                name: name.to_string(),
+                browser_download_url: String::new(),
+                size: 0,
+                digest: None,
+            });
+        }
+        let asset = release.find_asset("linux_x86_64").unwrap();
+        let names = release
+            .find_checksum_assets(asset)
+            .into_iter()
+            .map(|o| o.name.as_str())
+            .collect::<Vec<_>>();
+        expect_that!(
+            names,
+            elements_are![eq(&"checksums.txt"), eq(&"SHA256SUMS")]
+        );
+    }
+
+    #[gtest]
+    fn parse_checksum_file_works() {
+        let cases = [
+            (format!("{HASH}  tool.tar.gz\n"), Some(HASH)),
+            (format!("{HASH} *tool.tar.gz\n"), Some(HASH)),
+            (format!("{HASH}  ./dist/tool.tar.gz\n"), Some(HASH)),
+            (format!("SHA256 (tool.tar.gz) = {HASH}\n"), Some(HASH)),
+            (format!("{HASH}\n"), Some(HASH)),
+            (format!("{HASH}  other.tar.gz\n"), None),
+            ("abc  tool.tar.gz\n".to_string(), None),
+        ];
+        for (content, expected) in cases {
+            let res = parse_checksum_file(&content, "tool.tar.gz");
+            expect_that!(res.as_deref(), eq(expected), "Failed for {content:?}");
+        }
+    }
+
+    #[gtest]
+    fn verify_checksum_works() {
+        use ChecksumPolicy::*;
+        expect_that!(verify_checksum(Require, "a", Some(HASH), HASH), ok(none()));
+        expect_that!(verify_checksum(Warn, "a", None, HASH), ok(some(anything())));
+        expect_that!(verify_checksum(Off, "a", None, HASH), ok(none()));
+        expect_that!(
+            verify_checksum(Require, "a", None, HASH),
+            err(pat!(ChecksumError::Missing(_)))
+        );
+        expect_that!(
+            verify_checksum(Warn, "a", Some(&"0".repeat(64)), HASH),
+            err(pat!(ChecksumError::Mismatch { .. }))
+        );
+    }
+}
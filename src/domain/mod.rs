@@ -1,5 +1,11 @@
+mod checksum;
 mod release;
 mod repo;
+mod signature;
+mod version;
 
+pub use checksum::*;
 pub use release::*;
 pub use repo::*;
+pub use signature::*;
+pub use version::*;
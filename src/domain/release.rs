@@ -1,17 +1,20 @@
-use crate::utils::extract_version;
+use super::{ToolVersion, VersionError, VersionScheme};
+use itertools::Itertools;
 use jiff::Timestamp;
-use semver::Version;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Release {
     pub tag_name: String,
     pub body: String,
     pub created_at: Timestamp,
+    #[serde(default)]
+    pub prerelease: bool,
     pub assets: Vec<Asset>,
 }
 
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum FindAssetError {
     #[error("No asset found for pattern: {0:?}.")]
     NoAsset(String),
@@ -40,20 +43,50 @@ impl Release {
         Ok(res[0])
     }
 
-    pub fn version(&self) -> anyhow::Result<Version> {
-        match extract_version(&self.tag_name) {
+    pub fn version(&self, scheme: VersionScheme) -> Result<ToolVersion, VersionError> {
+        match scheme.extract(&self.tag_name) {
             Ok(version) => Ok(version),
-            _ => extract_version(&self.body),
+            _ => scheme.extract(&self.body),
         }
     }
+
+    /// Release notes as a markdown section
+    pub fn notes(&self) -> String {
+        let date = self.created_at.strftime("%Y-%m-%d");
+        format!("# {} ({date})\n\n{}\n", self.tag_name, self.body.trim())
+    }
+}
+
+/// Releases with a version in `(from, to]` sorted from newest to oldest, prereleases
+/// are ignored. Only the release matching `to` is returned if `from` is missing.
+pub fn releases_between<'a>(
+    releases: &'a [Release],
+    scheme: VersionScheme,
+    from: Option<&ToolVersion>,
+    to: &ToolVersion,
+) -> Vec<&'a Release> {
+    releases
+        .iter()
+        .filter(|o| !o.prerelease)
+        .filter_map(|o| o.version(scheme).ok().map(|version| (version, o)))
+        .filter(|(version, _)| match from {
+            Some(from) => version > from && version <= to,
+            None => version == to,
+        })
+        .sorted_by(|(a, _), (b, _)| b.cmp(a))
+        .map(|(_, o)| o)
+        .collect()
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Asset {
     pub name: String,
     pub browser_download_url: String,
     // File size given in bytes
     pub size: u64,
+    /// Digest in format "algorithm:hash" (eg: "sha256:abc...")
+    #[serde(default)]
+    pub digest: Option<String>,
 }
 
 #[cfg(test)]
@@ -89,19 +122,54 @@ mod tests {
         }
     }
 
+    #[gtest]
+    fn releases_between_works() {
+        let release = |tag: &str| Release {
+            tag_name: tag.to_string(),
+            body: format!("Notes for {tag}"),
+            created_at: Timestamp::UNIX_EPOCH,
+            prerelease: tag.contains("rc"),
+            assets: Vec::new(),
+        };
+        let releases = ["v1.3.0", "v1.3.0-rc1", "v1.2.0", "v1.1.0", "v1.0.0"].map(release);
+        let version = |s: &str| VersionScheme::Semver.extract(s).unwrap();
+        let tags = |from: Option<&str>, to: &str| {
+            let from = from.map(version);
+            releases_between(
+                &releases,
+                VersionScheme::Semver,
+                from.as_ref(),
+                &version(to),
+            )
+            .into_iter()
+            .map(|o| o.tag_name.as_str())
+            .collect::<Vec<_>>()
+        };
+        expect_that!(
+            tags(Some("1.0.0"), "1.3.0"),
+            elements_are![eq(&"v1.3.0"), eq(&"v1.2.0"), eq(&"v1.1.0")]
+        );
+        expect_that!(tags(Some("1.1.0"), "1.2.0"), elements_are![eq(&"v1.2.0")]);
+        expect_that!(tags(None, "1.3.0"), elements_are![eq(&"v1.3.0")]);
+        expect_that!(
+            release("v1.0.0").notes(),
+            eq("# v1.0.0 (1970-01-01)\n\nNotes for v1.0.0\n")
+        );
+    }
+
     #[gtest]
     fn get_version_works() {
         let cases = [
-            ("jesseduffield_lazydocker", "0.24.1"),
-            ("jesseduffield_lazygit", "0.54.1"),
-            ("rust-lang_rust-analyzer", "0.3.2563"),
+            ("jesseduffield_lazydocker", VersionScheme::Semver, "0.24.1"),
+            ("jesseduffield_lazygit", VersionScheme::Semver, "0.54.1"),
+            ("rust-lang_rust-analyzer", VersionScheme::Semver, "0.3.2563"),
+            ("rust-lang_rust-analyzer", VersionScheme::Date, "2025-08-04"),
         ];
-        for (name, expected) in cases {
+        for (name, scheme, expected) in cases {
             let s = read_to_string(format!("src/domain/test_files/{name}.json")).unwrap();
             let release = serde_json::from_str::<Release>(&s).unwrap();
-            let version = release.version();
-            let expected = Version::parse(expected).unwrap();
-            expect_that!(version, ok(eq(&expected)), "Failed for {name}");
+            let version = release.version(scheme).map(|o| o.to_string());
+            expect_that!(version, ok(eq(expected)), "Failed for {name}");
         }
     }
 }
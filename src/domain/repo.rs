@@ -1,19 +1,48 @@
-use anyhow::Context;
 use std::{fmt::Display, str::FromStr};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Repository {
     pub user: String,
     pub repository: String,
 }
 
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum RepositoryError {
+    #[error("Invalid repository {0:?}, expected user/repo_name.")]
+    Format(String),
+    #[error("Invalid user name {user:?} on {input:?}.")]
+    InvalidUser { user: String, input: String },
+    #[error("Invalid repository name {name:?} on {input:?}.")]
+    InvalidName { name: String, input: String },
+}
+
 impl FromStr for Repository {
-    type Err = anyhow::Error;
+    type Err = RepositoryError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (user, repository) = s.split_once('/').context("No delimiter '/' on input.")?;
+        let (user, repository) = s
+            .split_once('/')
+            .ok_or_else(|| RepositoryError::Format(s.to_string()))?;
         if user.contains('/') || repository.contains('/') {
-            anyhow::bail!("Invalid input.")
+            return Err(RepositoryError::Format(s.to_string()));
+        }
+        if user.is_empty() || !user.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(RepositoryError::InvalidUser {
+                user: user.to_string(),
+                input: s.to_string(),
+            });
+        }
+        // The repository name is also used as the binary name
+        if matches!(repository, "" | "." | "..")
+            || !repository
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        {
+            return Err(RepositoryError::InvalidName {
+                name: repository.to_string(),
+                input: s.to_string(),
+            });
         }
         Ok(Repository {
             user: user.to_string(),
@@ -27,3 +56,31 @@ impl Display for Repository {
         write!(f, "{}/{}", self.user, self.repository)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use googletest::prelude::*;
+
+    #[gtest]
+    fn from_str_works() {
+        expect_that!(
+            Repository::from_str("jesseduffield/lazygit"),
+            ok(eq(&Repository {
+                user: "jesseduffield".to_string(),
+                repository: "lazygit".to_string(),
+            }))
+        );
+        for s in [
+            "lazygit",
+            "a/b/c",
+            "/lazygit",
+            "user/",
+            "user/..",
+            "us er/tool",
+            "user/to ol",
+        ] {
+            expect_that!(Repository::from_str(s), err(anything()), "Failed for {s:?}");
+        }
+    }
+}
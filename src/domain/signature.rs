@@ -0,0 +1,294 @@
+use super::{Asset, Release};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use p256::{
+    ecdsa::{Signature, VerifyingKey, signature::Verifier},
+    pkcs8::DecodePublicKey,
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Signature verification settings for a repository
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SignatureConfig {
+    /// Verify `<asset>.minisig` with a minisign public key (eg: "RWQ...")
+    Minisign {
+        public_key: Option<String>,
+        /// Path to the public key, relative to the config file
+        public_key_file: Option<PathBuf>,
+    },
+    /// Verify `<asset>.bundle`, `<asset>.sigstore.json` or `<asset>.sig` with a
+    /// cosign ECDSA P-256 public key, PEM encoded. Keyless signatures aren't supported.
+    Cosign {
+        public_key: Option<String>,
+        /// Path to the public key, relative to the config file
+        public_key_file: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum SignatureError {
+    #[error("No signature asset found for {0:?}.")]
+    Missing(String),
+    #[error(
+        "Either `public_key` or `public_key_file` should be set, keyless verification isn't supported."
+    )]
+    NoPublicKey,
+    #[error("Only one of `public_key` and `public_key_file` should be set.")]
+    PublicKeyConflict,
+    #[error("Failed to read public key: {0}.")]
+    InvalidPublicKey(String),
+    #[error("{0:?} is signed keyless with a certificate, which isn't supported.")]
+    Keyless(String),
+    #[error("Failed to decode signature: {0}.")]
+    InvalidSignature(String),
+    #[error("Signature verification failed for {0:?}.")]
+    Verification(String),
+}
+
+impl SignatureConfig {
+    /// Extensions of the signature assets, in order of preference
+    fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            SignatureConfig::Minisign { .. } => &["minisig"],
+            SignatureConfig::Cosign { .. } => &["bundle", "sigstore.json", "sig"],
+        }
+    }
+
+    /// Reads the public key, either inline or from `public_key_file`
+    pub fn public_key(&self) -> Result<String, SignatureError> {
+        let (SignatureConfig::Minisign {
+            public_key,
+            public_key_file,
+        }
+        | SignatureConfig::Cosign {
+            public_key,
+            public_key_file,
+        }) = self;
+        match (public_key, public_key_file) {
+            (Some(public_key), None) => Ok(public_key.clone()),
+            (None, Some(path)) => std::fs::read_to_string(path)
+                .map_err(|e| SignatureError::InvalidPublicKey(format!("{path:?}: {e}"))),
+            (None, None) => Err(SignatureError::NoPublicKey),
+            (Some(_), Some(_)) => Err(SignatureError::PublicKeyConflict),
+        }
+    }
+
+    /// Verifies `data` (the content of `name`) against the content of its signature asset
+    pub fn verify(&self, name: &str, data: &[u8], signature: &str) -> Result<(), SignatureError> {
+        let public_key = self.public_key()?;
+        match self {
+            SignatureConfig::Minisign { .. } => {
+                let public_key = public_key.trim();
+                let public_key = if public_key.lines().count() > 1 {
+                    minisign_verify::PublicKey::decode(public_key)
+                } else {
+                    minisign_verify::PublicKey::from_base64(public_key)
+                }
+                .map_err(|e| SignatureError::InvalidPublicKey(e.to_string()))?;
+                let signature = minisign_verify::Signature::decode(signature)
+                    .map_err(|e| SignatureError::InvalidSignature(e.to_string()))?;
+                public_key
+                    .verify(data, &signature, false)
+                    .map_err(|_| SignatureError::Verification(name.to_string()))
+            }
+            SignatureConfig::Cosign { .. } => {
+                let public_key = VerifyingKey::from_public_key_pem(public_key.trim())
+                    .map_err(|e| SignatureError::InvalidPublicKey(e.to_string()))?;
+                let signature = decode_cosign_signature(name, signature)?;
+                public_key
+                    .verify(data, &signature)
+                    .map_err(|_| SignatureError::Verification(name.to_string()))
+            }
+        }
+    }
+}
+
+impl Release {
+    /// Find the signature asset for `asset` based on the signature settings
+    pub fn find_signature_asset(&self, asset: &Asset, config: &SignatureConfig) -> Option<&Asset> {
+        config.extensions().iter().find_map(|ext| {
+            let name = format!("{}.{ext}", asset.name);
+            self.assets.iter().find(|o| o.name == name)
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CosignBundle {
+    /// Bundle created by `cosign sign-blob --bundle`
+    base64_signature: Option<String>,
+    /// Signing certificate of a cosign bundle, empty when signed with a key
+    #[serde(default)]
+    cert: String,
+    /// Sigstore bundle format (eg: "*.sigstore.json")
+    message_signature: Option<MessageSignature>,
+    verification_material: Option<VerificationMaterial>,
+}
+
+#[derive(Deserialize)]
+struct MessageSignature {
+    signature: String,
+}
+
+/// Key material of a sigstore bundle, which has a certificate when signed keyless
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VerificationMaterial {
+    certificate: Option<serde_json::Value>,
+    x509_certificate_chain: Option<serde_json::Value>,
+}
+
+impl CosignBundle {
+    fn is_keyless(&self) -> bool {
+        !self.cert.is_empty()
+            || self
+                .verification_material
+                .as_ref()
+                .is_some_and(|o| o.certificate.is_some() || o.x509_certificate_chain.is_some())
+    }
+}
+
+/// Decodes the cosign signature of `name`, either a base64 DER signature or a json
+/// bundle
+fn decode_cosign_signature(name: &str, s: &str) -> Result<Signature, SignatureError> {
+    let s = s.trim();
+    let b64 = if s.starts_with('{') {
+        let bundle = serde_json::from_str::<CosignBundle>(s)
+            .map_err(|e| SignatureError::InvalidSignature(e.to_string()))?;
+        // The signature would be checked against the configured key and not the
+        // certificate, which needs the sigstore trust root to be verified
+        if bundle.is_keyless() {
+            return Err(SignatureError::Keyless(name.to_string()));
+        }
+        bundle
+            .base64_signature
+            .or(bundle.message_signature.map(|o| o.signature))
+            .ok_or_else(|| SignatureError::InvalidSignature("No signature in bundle".into()))?
+    } else {
+        s.to_string()
+    };
+    let der = BASE64_STANDARD
+        .decode(b64.trim())
+        .map_err(|e| SignatureError::InvalidSignature(e.to_string()))?;
+    Signature::from_der(&der).map_err(|e| SignatureError::InvalidSignature(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use googletest::prelude::*;
+    use p256::{
+        ecdsa::{SigningKey, signature::Signer},
+        pkcs8::{EncodePublicKey, LineEnding},
+    };
+
+    const MINISIGN_PUBLIC_KEY: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+    const MINISIGN_SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1556193335\tfile:test
+y/rUw2y8/hOUYjZU71eHp/Wo1KZ40fGy2VJEDl34XMJM+TX48Ss/17u3IvIfbVR1FkZZSNCisQbuQY+bHwhEBg==";
+
+    #[gtest]
+    fn minisign_verify_works() {
+        let config = SignatureConfig::Minisign {
+            public_key: Some(MINISIGN_PUBLIC_KEY.to_string()),
+            public_key_file: None,
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let public_key_file = dir.path().join("minisign.pub");
+        std::fs::write(&public_key_file, MINISIGN_PUBLIC_KEY).unwrap();
+        let from_file = SignatureConfig::Minisign {
+            public_key: None,
+            public_key_file: Some(public_key_file),
+        };
+        let no_key = SignatureConfig::Minisign {
+            public_key: None,
+            public_key_file: None,
+        };
+        expect_that!(config.verify("test", b"test", MINISIGN_SIGNATURE), ok(()));
+        expect_that!(
+            from_file.verify("test", b"test", MINISIGN_SIGNATURE),
+            ok(())
+        );
+        expect_that!(
+            no_key.verify("test", b"test", MINISIGN_SIGNATURE),
+            err(pat!(SignatureError::NoPublicKey))
+        );
+        expect_that!(
+            config.verify("test", b"Test", MINISIGN_SIGNATURE),
+            err(pat!(SignatureError::Verification(_)))
+        );
+    }
+
+    #[gtest]
+    fn cosign_verify_works() {
+        let signing_key = SigningKey::from_slice(&[7; 32]).unwrap();
+        let public_key = signing_key
+            .verifying_key()
+            .to_public_key_pem(LineEnding::LF)
+            .unwrap();
+        let signature: Signature = signing_key.sign(b"test");
+        let signature = BASE64_STANDARD.encode(signature.to_der());
+        let bundle = format!(r#"{{"base64Signature": "{signature}", "cert": ""}}"#);
+        let sigstore_bundle = format!(r#"{{"messageSignature": {{"signature": "{signature}"}}}}"#);
+        let keyless_bundle = format!(r#"{{"base64Signature": "{signature}", "cert": "LS0t"}}"#);
+        let keyless_sigstore_bundle = format!(
+            r#"{{"messageSignature": {{"signature": "{signature}"}}, "verificationMaterial": {{"certificate": {{"rawBytes": "MII="}}}}}}"#
+        );
+        let config = SignatureConfig::Cosign {
+            public_key: Some(public_key),
+            public_key_file: None,
+        };
+        for s in [&keyless_bundle, &keyless_sigstore_bundle] {
+            expect_that!(
+                config.verify("test", b"test", s),
+                err(pat!(SignatureError::Keyless(eq("test")))),
+                "Failed for {s}"
+            );
+        }
+        for s in [&signature, &bundle, &sigstore_bundle] {
+            expect_that!(config.verify("test", b"test", s), ok(()), "Failed for {s}");
+            expect_that!(
+                config.verify("test", b"Test", s),
+                err(pat!(SignatureError::Verification(_))),
+                "Failed for {s}"
+            );
+        }
+    }
+
+    #[gtest]
+    fn find_signature_asset_works() {
+        let asset = |name: &str| Asset {
+            name: name.to_string(),
+            browser_download_url: String::new(),
+            size: 0,
+            digest: None,
+        };
+        let release = Release {
+            tag_name: "v1.0.0".to_string(),
+            body: String::new(),
+            created_at: jiff::Timestamp::UNIX_EPOCH,
+            prerelease: false,
+            assets: ["tool.tar.gz", "tool.tar.gz.sig", "tool.tar.gz.bundle"]
+                .map(asset)
+                .to_vec(),
+        };
+        let minisign = SignatureConfig::Minisign {
+            public_key: None,
+            public_key_file: None,
+        };
+        let cosign = SignatureConfig::Cosign {
+            public_key: None,
+            public_key_file: None,
+        };
+        let target = &release.assets[0];
+        expect_that!(release.find_signature_asset(target, &minisign), none());
+        expect_that!(
+            release.find_signature_asset(target, &cosign),
+            some(field!(Asset.name, eq("tool.tar.gz.bundle")))
+        );
+    }
+}
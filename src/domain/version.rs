@@ -0,0 +1,258 @@
+use itertools::Itertools;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, fmt::Display, str::FromStr, sync::LazyLock};
+
+/// Versioning scheme used by a repository to tag releases and report versions
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VersionScheme {
+    /// Exactly three numeric components (eg: "v1.2.3")
+    #[default]
+    Semver,
+    /// One to three numeric components, missing ones are set to zero (eg: "v1.2", "r27")
+    Loose,
+    /// Calendar versions with any number of components (eg: "2024.10.03", "24.04")
+    Calver,
+    /// Plain dates (eg: "2025-08-11", "nightly-2025-01-01")
+    Date,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum VersionError {
+    #[error("No {scheme:?} version found on: {input:?}.")]
+    NotFound {
+        scheme: VersionScheme,
+        input: String,
+    },
+    #[error("Failed to parse version: {0:?}.")]
+    Invalid(String),
+}
+
+/// A version following any of the supported [`VersionScheme`]s
+#[derive(Clone, Debug)]
+pub enum ToolVersion {
+    Semver(semver::Version),
+    /// Numeric components compared in order, keeping the original text for display
+    Calendar {
+        parts: Vec<u64>,
+        raw: String,
+    },
+}
+
+impl VersionScheme {
+    fn regex(&self) -> &'static Regex {
+        static SEMVER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(\d+\.\d+\.\d+)").unwrap());
+        static LOOSE: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"(\d+(?:\.\d+){0,2})").unwrap());
+        static CALVER: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"(\d{2,4}\.\d{1,2}(?:\.\d+)*)").unwrap());
+        static DATE: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"(\d{4}[-_.]\d{2}[-_.]\d{2})").unwrap());
+        match self {
+            VersionScheme::Semver => &SEMVER,
+            VersionScheme::Loose => &LOOSE,
+            VersionScheme::Calver => &CALVER,
+            VersionScheme::Date => &DATE,
+        }
+    }
+
+    /// Finds the first version in `s`
+    pub fn extract(&self, s: &str) -> Result<ToolVersion, VersionError> {
+        let version = self
+            .regex()
+            .captures(s)
+            .and_then(|o| o.get(1))
+            .ok_or_else(|| VersionError::NotFound {
+                scheme: *self,
+                input: s.to_string(),
+            })?
+            .as_str();
+        self.parse(version)
+    }
+
+    /// Parses a string containing only the version
+    fn parse(&self, s: &str) -> Result<ToolVersion, VersionError> {
+        match self {
+            VersionScheme::Semver => semver::Version::parse(s)
+                .map(ToolVersion::Semver)
+                .map_err(|_| VersionError::Invalid(s.to_string())),
+            VersionScheme::Loose => {
+                let parts = parse_parts(s)?;
+                let part = |i| parts.get(i).copied().unwrap_or(0);
+                Ok(ToolVersion::Semver(semver::Version::new(
+                    part(0),
+                    part(1),
+                    part(2),
+                )))
+            }
+            VersionScheme::Calver | VersionScheme::Date => Ok(ToolVersion::Calendar {
+                parts: parse_parts(s)?,
+                raw: s.to_string(),
+            }),
+        }
+    }
+}
+
+fn parse_parts(s: &str) -> Result<Vec<u64>, VersionError> {
+    s.split(['.', '-', '_'])
+        .map(|o| o.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| VersionError::Invalid(s.to_string()))
+}
+
+impl ToolVersion {
+    /// Numeric components used to compare versions of different kinds
+    fn parts(&self) -> Vec<u64> {
+        match self {
+            ToolVersion::Semver(v) => vec![v.major, v.minor, v.patch],
+            ToolVersion::Calendar { parts, .. } => parts.clone(),
+        }
+    }
+
+    /// Prerelease, sorted before the release, and build metadata of the version,
+    /// calendar versions have neither
+    fn metadata(&self) -> (bool, semver::Prerelease, semver::BuildMetadata) {
+        match self {
+            ToolVersion::Semver(v) => (v.pre.is_empty(), v.pre.clone(), v.build.clone()),
+            ToolVersion::Calendar { .. } => (
+                true,
+                semver::Prerelease::EMPTY,
+                semver::BuildMetadata::EMPTY,
+            ),
+        }
+    }
+
+    /// Name of the directory keeping this version, the same for equal versions
+    /// written differently (eg: "2025-08-04" and "2025_08_04")
+    pub fn dir_name(&self) -> String {
+        match self {
+            ToolVersion::Semver(v) => v.to_string(),
+            ToolVersion::Calendar { parts, .. } => parts.iter().join("."),
+        }
+    }
+}
+
+impl PartialEq for ToolVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ToolVersion {}
+
+impl PartialOrd for ToolVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ToolVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Same order as semver for semver versions
+        self.parts()
+            .cmp(&other.parts())
+            .then_with(|| self.metadata().cmp(&other.metadata()))
+    }
+}
+
+impl Display for ToolVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ToolVersion::Semver(v) => write!(f, "{v}"),
+            ToolVersion::Calendar { raw, .. } => write!(f, "{raw}"),
+        }
+    }
+}
+
+impl FromStr for ToolVersion {
+    type Err = VersionError;
+
+    /// Parses a version written by [`Display`], guessing its scheme
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim_start_matches('v');
+        VersionScheme::Semver
+            .parse(s)
+            .or_else(|_| VersionScheme::Calver.parse(s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use googletest::prelude::*;
+
+    #[gtest]
+    fn extract_works() {
+        let cases = [
+            (VersionScheme::Semver, "v1.2.3", "1.2.3"),
+            (VersionScheme::Loose, "v1.2", "1.2.0"),
+            (VersionScheme::Loose, "r27", "27.0.0"),
+            (VersionScheme::Calver, "release 2024.10.03", "2024.10.03"),
+            (VersionScheme::Calver, "24.04", "24.04"),
+            (VersionScheme::Date, "nightly-2025-01-01", "2025-01-01"),
+            (VersionScheme::Date, "2025-08-11", "2025-08-11"),
+        ];
+        for (scheme, s, expected) in cases {
+            let version = scheme.extract(s).map(|o| o.to_string());
+            expect_that!(version, ok(eq(expected)), "Failed for {scheme:?} {s:?}");
+        }
+        expect_that!(VersionScheme::Semver.extract("v1.2"), err(anything()));
+        expect_that!(VersionScheme::Date.extract("v1.2.3"), err(anything()));
+    }
+
+    #[gtest]
+    fn compare_works() {
+        let cases = [
+            (VersionScheme::Semver, "0.9.0", "0.10.0"),
+            (VersionScheme::Loose, "v1.2", "v1.10"),
+            (VersionScheme::Calver, "2024.9.30", "2024.10.03"),
+            (
+                VersionScheme::Date,
+                "nightly-2024-12-31",
+                "nightly-2025-01-01",
+            ),
+        ];
+        for (scheme, old, new) in cases {
+            let old = scheme.extract(old).unwrap();
+            let new = scheme.extract(new).unwrap();
+            expect_that!(new, gt(&old), "Failed for {scheme:?}");
+        }
+        let a = VersionScheme::Date.extract("2025-08-04").unwrap();
+        let b = VersionScheme::Date.extract("2025_08_04").unwrap();
+        expect_that!(a, eq(&b));
+        expect_that!(a.dir_name(), eq(&b.dir_name()));
+    }
+
+    #[gtest]
+    fn compare_across_kinds_works() {
+        let semver = |s: &str| ToolVersion::Semver(semver::Version::parse(s).unwrap());
+        let rc = semver("1.0.0-rc.1");
+        let release = semver("1.0.0");
+        let calendar = ToolVersion::Calendar {
+            parts: vec![1, 0, 0],
+            raw: "1.0.0".to_string(),
+        };
+        expect_that!(rc, lt(&release));
+        expect_that!(rc, lt(&calendar));
+        expect_that!(calendar, eq(&release));
+        let mut versions = [release, calendar, rc.clone()];
+        versions.sort();
+        expect_that!(versions[0], eq(&rc));
+    }
+
+    #[gtest]
+    fn from_str_roundtrip_works() {
+        for (scheme, s) in [
+            (VersionScheme::Semver, "1.2.3"),
+            (VersionScheme::Loose, "v1.2"),
+            (VersionScheme::Calver, "2024.10.03"),
+            (VersionScheme::Date, "2025-08-11"),
+        ] {
+            let version = scheme.extract(s).unwrap();
+            let parsed = ToolVersion::from_str(&version.to_string());
+            expect_that!(parsed, ok(eq(&version)), "Failed for {s:?}");
+        }
+    }
+}
@@ -1,84 +1,558 @@
-use crate::domain::{Asset, Release, Repository};
-use anyhow::Context;
+use crate::{
+    domain::{
+        Asset, Release, Repository, ToolVersion, VersionScheme, parse_checksum_file,
+        releases_between,
+    },
+    reporter::Reporter,
+};
 use futures::StreamExt;
-use indicatif::{ProgressBar, ProgressStyle};
-use reqwest::Client;
-use std::path::{Path, PathBuf};
+use itertools::Itertools;
+use reqwest::{
+    Certificate, Client, NoProxy, Proxy, StatusCode,
+    header::{AUTHORIZATION, HeaderMap, HeaderValue, RANGE},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 use tokio::{
-    fs::File,
-    io::{AsyncWriteExt, BufWriter},
+    fs::{OpenOptions, copy, create_dir_all, metadata, read, write},
+    io::{AsyncSeekExt, AsyncWriteExt, BufWriter},
+    time::sleep,
 };
 
+const MAX_RETRIES: u32 = 5;
+const RELEASES_PER_PAGE: u32 = 100;
+const MAX_RELEASES_PAGES: u32 = 10;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum GithubError {
+    #[error("Failed to build client.")]
+    Client(#[source] reqwest::Error),
+    #[error("Invalid token.")]
+    InvalidToken,
+    #[error("Request failed.")]
+    Request(#[from] reqwest::Error),
+    #[error("Failed to parse response.")]
+    Json(#[from] serde_json::Error),
+    #[error("Failed to write to {path:?}.")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Downloaded {downloaded} bytes but expected {expected} for {name:?}.")]
+    SizeMismatch {
+        name: String,
+        downloaded: u64,
+        expected: u64,
+    },
+    #[error("Download incomplete after {MAX_RETRIES} retries: {downloaded}/{expected} bytes.")]
+    Incomplete { downloaded: u64, expected: u64 },
+    #[error("Download failed after {MAX_RETRIES} retries.")]
+    RetriesExhausted(#[source] Box<GithubError>),
+    #[error("Request to {url:?} failed with status {status}.")]
+    Status { url: String, status: u16 },
+    #[error("No recorded response for {url:?} in {path:?}.")]
+    NotRecorded { url: String, path: PathBuf },
+    #[error("Invalid proxy {url:?}.")]
+    Proxy { url: String, source: reqwest::Error },
+    #[error("Failed to read CA bundle {path:?}.")]
+    ReadCaBundle {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Invalid certificates in {path:?}.")]
+    Certificates {
+        path: PathBuf,
+        source: reqwest::Error,
+    },
+}
+
+/// Network options of a [`GithubClient`]
+#[derive(Clone, Debug)]
+pub struct ClientOptions {
+    /// Proxy used for every request instead of the ones of the `HTTP_PROXY`,
+    /// `HTTPS_PROXY` and `ALL_PROXY` environment variables
+    pub proxy: Option<String>,
+    /// Hosts reached without `proxy`, in the `NO_PROXY` format
+    pub no_proxy: Option<String>,
+    /// PEM file with root certificates to trust besides the bundled ones
+    pub ca_bundle: Option<PathBuf>,
+    /// Trust the certificates of the system store besides the bundled ones
+    pub system_certs: bool,
+    pub connect_timeout: Option<Duration>,
+    /// Maximum time waiting for data, it doesn't limit the duration of downloads
+    pub read_timeout: Option<Duration>,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            proxy: None,
+            no_proxy: None,
+            ca_bundle: None,
+            system_certs: true,
+            connect_timeout: None,
+            read_timeout: None,
+        }
+    }
+}
+
+/// Whether a [`GithubClient`] saves or serves its responses from a directory, so
+/// problems can be reproduced offline
+#[derive(Clone, Debug)]
+pub enum Capture {
+    /// Save every API response and downloaded asset into the directory
+    Record(PathBuf),
+    /// Serve every API response and asset from a directory made with
+    /// [`Capture::Record`] instead of doing requests
+    Replay(PathBuf),
+}
+
+#[derive(Clone)]
 pub struct GithubClient {
     client: Client,
+    /// Base URL of the API (eg: "https://api.github.com")
+    api_url: String,
+    capture: Option<Capture>,
 }
 
 impl GithubClient {
-    pub fn new() -> anyhow::Result<Self> {
-        let client = Client::builder()
+    pub fn new(
+        api_url: &str,
+        token: Option<&str>,
+        options: &ClientOptions,
+    ) -> Result<Self, GithubError> {
+        let mut headers = HeaderMap::new();
+        if let Some(token) = token {
+            let mut value = HeaderValue::from_str(&format!("Bearer {token}"))
+                .map_err(|_| GithubError::InvalidToken)?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+        let mut builder = Client::builder()
             .user_agent("dl-releases")
-            .build()
-            .context("Failed to build client.")?;
-        Ok(Self { client })
+            .default_headers(headers)
+            .use_rustls_tls()
+            .tls_built_in_webpki_certs(true)
+            .tls_built_in_native_certs(options.system_certs);
+        // Without a proxy in `options`, reqwest uses the ones of the environment
+        if let Some(url) = &options.proxy {
+            let proxy = Proxy::all(url).map_err(|source| GithubError::Proxy {
+                url: url.clone(),
+                source,
+            })?;
+            let no_proxy = options.no_proxy.as_deref().and_then(NoProxy::from_string);
+            builder = builder.no_proxy().proxy(proxy.no_proxy(no_proxy));
+        }
+        if let Some(path) = &options.ca_bundle {
+            let pem = std::fs::read(path).map_err(|source| GithubError::ReadCaBundle {
+                path: path.clone(),
+                source,
+            })?;
+            let certificates =
+                Certificate::from_pem_bundle(&pem).map_err(|source| GithubError::Certificates {
+                    path: path.clone(),
+                    source,
+                })?;
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        if let Some(timeout) = options.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = options.read_timeout {
+            builder = builder.read_timeout(timeout);
+        }
+        let client = builder.build().map_err(GithubError::Client)?;
+        Ok(Self {
+            client,
+            api_url: api_url.trim_end_matches('/').to_string(),
+            capture: None,
+        })
+    }
+
+    pub fn with_capture(mut self, capture: Capture) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
+    pub fn capture(&self) -> Option<&Capture> {
+        self.capture.as_ref()
     }
 
-    pub async fn get_latest_release(&self, repo: &Repository) -> anyhow::Result<Release> {
+    /// GETs `url`, recording or replaying the response depending on the capture mode
+    async fn get(&self, url: &str) -> Result<Vec<u8>, GithubError> {
+        if let Some(Capture::Replay(dir)) = &self.capture {
+            return read_capture(dir, url).await;
+        }
+        let response = self.client.get(url).send().await?;
+        let status = response.status();
+        let bytes = response.bytes().await?;
+        if let Some(Capture::Record(dir)) = &self.capture {
+            write_capture(dir, url, status.as_u16(), &bytes).await?;
+        }
+        if !status.is_success() {
+            return Err(GithubError::Status {
+                url: url.to_string(),
+                status: status.as_u16(),
+            });
+        }
+        Ok(bytes.to_vec())
+    }
+
+    pub async fn get_latest_release(&self, repo: &Repository) -> Result<Release, GithubError> {
+        let url = format!(
+            "{}/repos/{}/{}/releases/latest",
+            self.api_url, repo.user, repo.repository
+        );
+        let release = serde_json::from_slice(&self.get(&url).await?)?;
+        Ok(release)
+    }
+
+    pub async fn get_release_by_tag(
+        &self,
+        repo: &Repository,
+        tag: &str,
+    ) -> Result<Release, GithubError> {
         let url = format!(
-            "https://api.github.com/repos/{}/{}/releases/latest",
-            repo.user, repo.repository
+            "{}/repos/{}/{}/releases/tags/{tag}",
+            self.api_url, repo.user, repo.repository
         );
-        let raw_response = self
-            .client
-            .get(&url)
-            .send()
-            .await?
-            .error_for_status()?
-            .json::<serde_json::Value>()
-            .await?;
-        #[cfg(debug_assertions)]
-        {
-            use tokio::fs::{create_dir, write};
-
-            let s = serde_json::to_string_pretty(&raw_response)?;
-            let path = Path::new("raw_outputs");
-            if !path.exists() {
-                create_dir(path).await?;
+        let release = serde_json::from_slice(&self.get(&url).await?)?;
+        Ok(release)
+    }
+
+    /// Fetches one page of releases, sorted from newest to oldest
+    pub async fn get_releases(
+        &self,
+        repo: &Repository,
+        page: u32,
+    ) -> Result<Vec<Release>, GithubError> {
+        let url = format!(
+            "{}/repos/{}/{}/releases?per_page={RELEASES_PER_PAGE}&page={page}",
+            self.api_url, repo.user, repo.repository
+        );
+        let releases = serde_json::from_slice(&self.get(&url).await?)?;
+        Ok(releases)
+    }
+
+    /// Release notes of every release in `(from, to]`, newest first. Only the notes for
+    /// `to` are returned if `from` is missing.
+    pub async fn get_changelog(
+        &self,
+        repo: &Repository,
+        scheme: VersionScheme,
+        from: Option<&ToolVersion>,
+        to: &ToolVersion,
+    ) -> Result<String, GithubError> {
+        let mut releases = Vec::new();
+        for page in 1..=MAX_RELEASES_PAGES {
+            let batch = self.get_releases(repo, page).await?;
+            let done = batch.len() < RELEASES_PER_PAGE as usize
+                || batch
+                    .iter()
+                    .filter_map(|o| o.version(scheme).ok())
+                    .any(|o| from.is_none_or(|from| &o <= from));
+            releases.extend(batch);
+            if done {
+                break;
             }
-            let filename = repo.to_string().replace('/', "_");
-            write(path.join(format!("{filename}.json")), s).await?;
         }
-        let release = serde_json::from_value(raw_response)?;
-        Ok(release)
+        let notes = releases_between(&releases, scheme, from, to)
+            .into_iter()
+            .map(Release::notes)
+            .join("\n");
+        Ok(notes)
+    }
+
+    /// Downloads the content of a small text asset (eg: checksums or signatures)
+    pub async fn get_asset_text(&self, asset: &Asset) -> Result<String, GithubError> {
+        let bytes = self.get(&asset.browser_download_url).await?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Finds the expected sha256 for `asset`, either from the digest reported by the
+    /// API or from checksum files published in the release
+    pub async fn find_checksum(
+        &self,
+        release: &Release,
+        asset: &Asset,
+    ) -> Result<Option<String>, GithubError> {
+        if let Some(digest) = asset.sha256() {
+            return Ok(Some(digest));
+        }
+        for checksum_asset in release.find_checksum_assets(asset) {
+            let content = self.get_asset_text(checksum_asset).await?;
+            if let Some(hash) = parse_checksum_file(&content, &asset.name) {
+                return Ok(Some(hash));
+            }
+        }
+        Ok(None)
     }
 
     pub async fn download_asset(
         &self,
-        repo: &Repository,
         asset: &Asset,
         outpath: &Path,
-        pb: &ProgressBar,
-    ) -> anyhow::Result<PathBuf> {
-        pb.set_message(asset.name.clone());
+        reporter: &dyn Reporter,
+    ) -> Result<DownloadedAsset, GithubError> {
         let path = outpath.join(&asset.name);
-        pb.set_message(format!("Downloading {}", repo.repository));
-        let file = File::create(&path)
+        reporter.download_started(asset);
+        if let Some(Capture::Replay(dir)) = &self.capture {
+            return replay_asset(dir, asset, path, reporter).await;
+        }
+        let mut state = DownloadState::default();
+        let mut attempt = 0;
+        loop {
+            let res = self
+                .download_chunks(&asset.browser_download_url, &path, &mut state, reporter)
+                .await;
+            let downloaded = state.downloaded;
+            match res {
+                Ok(()) if downloaded == asset.size => break,
+                Ok(()) if downloaded > asset.size => {
+                    return Err(GithubError::SizeMismatch {
+                        name: asset.name.clone(),
+                        downloaded,
+                        expected: asset.size,
+                    });
+                }
+                Ok(()) => {
+                    if attempt >= MAX_RETRIES {
+                        return Err(GithubError::Incomplete {
+                            downloaded,
+                            expected: asset.size,
+                        });
+                    }
+                }
+                Err(e) if !is_retryable(&e) => return Err(e),
+                Err(e) => {
+                    if attempt >= MAX_RETRIES {
+                        return Err(GithubError::RetriesExhausted(Box::new(e)));
+                    }
+                }
+            }
+            let delay = INITIAL_BACKOFF * 2u32.pow(attempt);
+            attempt += 1;
+            reporter.download_retrying(attempt, MAX_RETRIES, delay);
+            sleep(delay).await;
+        }
+        let len = metadata(&path).await.map(|o| o.len()).unwrap_or(0);
+        if len != asset.size {
+            return Err(GithubError::SizeMismatch {
+                name: asset.name.clone(),
+                downloaded: len,
+                expected: asset.size,
+            });
+        }
+        if let Some(Capture::Record(dir)) = &self.capture {
+            let url = &asset.browser_download_url;
+            write_capture_entry(dir, url, StatusCode::OK.as_u16()).await?;
+            let capture = capture_path(dir, url).with_extension(BODY_EXTENSION);
+            copy(&path, &capture)
+                .await
+                .map_err(|source| GithubError::Io {
+                    path: capture,
+                    source,
+                })?;
+        }
+        reporter.download_finished(&path);
+        Ok(DownloadedAsset {
+            path,
+            sha256: hex::encode(state.hasher.finalize()),
+        })
+    }
+
+    /// Streams `url` into `path`, resuming from the bytes already in `state` with a
+    /// `Range` request when possible.
+    async fn download_chunks(
+        &self,
+        url: &str,
+        path: &Path,
+        state: &mut DownloadState,
+        reporter: &dyn Reporter,
+    ) -> Result<(), GithubError> {
+        let offset = state.downloaded;
+        let mut request = self.client.get(url);
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={offset}-"));
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            if let Some(Capture::Record(dir)) = &self.capture {
+                write_capture(dir, url, status.as_u16(), &response.bytes().await?).await?;
+            }
+            return Err(GithubError::Status {
+                url: url.to_string(),
+                status: status.as_u16(),
+            });
+        }
+        // Servers that ignore the `Range` header send the whole file again
+        let resume = offset > 0 && response.status() == StatusCode::PARTIAL_CONTENT;
+        let io_error = |source| GithubError::Io {
+            path: path.to_owned(),
+            source,
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(!resume)
+            .open(path)
             .await
-            .with_context(|| format!("Failed to create file: {path:?}."))?;
+            .map_err(io_error)?;
+        if resume {
+            // Drop any bytes written after the last hashed chunk
+            file.set_len(offset).await.map_err(io_error)?;
+            file.seek(SeekFrom::End(0)).await.map_err(io_error)?;
+        } else {
+            *state = DownloadState::default();
+        }
         let mut writer = BufWriter::new(file);
-        let response = self.client.get(&asset.browser_download_url).send().await?;
-        let mut downloaded = 0u64;
+        reporter.download_progress(state.downloaded);
         let mut stream = response.bytes_stream();
-        while let Some(Ok(chunk)) = stream.next().await {
-            writer.write_all(&chunk).await?;
-            downloaded += chunk.len() as u64;
-            pb.set_position(downloaded);
-        }
-        writer.flush().await?;
-        pb.set_style(ProgressStyle::with_template("{msg:.green} {bytes}").unwrap());
-        pb.finish_with_message(format!(
-            "✓ [{}] Downloaded to {outpath:?}.",
-            repo.repository
-        ));
-        Ok(path)
+        while let Some(chunk) = stream.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    writer.flush().await.map_err(io_error)?;
+                    return Err(e.into());
+                }
+            };
+            writer.write_all(&chunk).await.map_err(io_error)?;
+            state.hasher.update(&chunk);
+            state.downloaded += chunk.len() as u64;
+            reporter.download_progress(state.downloaded);
+        }
+        writer.flush().await.map_err(io_error)?;
+        Ok(())
+    }
+}
+
+/// A downloaded asset and the sha256 of its content
+#[derive(Clone, Debug)]
+pub struct DownloadedAsset {
+    pub path: PathBuf,
+    pub sha256: String,
+}
+
+#[derive(Default)]
+struct DownloadState {
+    downloaded: u64,
+    hasher: Sha256,
+}
+
+/// Client errors (eg: 404) won't be fixed by retrying
+fn is_retryable(e: &GithubError) -> bool {
+    match e {
+        GithubError::Request(e) => e.status().is_none_or(|o| !o.is_client_error()),
+        GithubError::Status { status, .. } => !(400..500).contains(status),
+        GithubError::NotRecorded { .. } => false,
+        _ => true,
+    }
+}
+
+/// Recorded response of a request, next to its body in a file with the same name
+/// and [`BODY_EXTENSION`]
+#[derive(Serialize, Deserialize)]
+struct CaptureEntry {
+    method: String,
+    url: String,
+    status: u16,
+}
+
+const BODY_EXTENSION: &str = "body";
+
+/// File keeping the [`CaptureEntry`] of the GET request to `url` in a capture
+/// directory, named after a hash of the method and the full url
+fn capture_path(dir: &Path, url: &str) -> PathBuf {
+    let hash = hex::encode(Sha256::digest(format!("GET {url}")));
+    dir.join(format!("{hash}.json"))
+}
+
+async fn write_capture_entry(dir: &Path, url: &str, status: u16) -> Result<(), GithubError> {
+    create_dir_all(dir)
+        .await
+        .map_err(|source| GithubError::Io {
+            path: dir.to_owned(),
+            source,
+        })?;
+    let entry = CaptureEntry {
+        method: "GET".to_string(),
+        url: url.to_string(),
+        status,
+    };
+    let path = capture_path(dir, url);
+    write(&path, serde_json::to_vec_pretty(&entry)?)
+        .await
+        .map_err(|source| GithubError::Io { path, source })
+}
+
+async fn write_capture(
+    dir: &Path,
+    url: &str,
+    status: u16,
+    bytes: &[u8],
+) -> Result<(), GithubError> {
+    write_capture_entry(dir, url, status).await?;
+    let path = capture_path(dir, url).with_extension(BODY_EXTENSION);
+    write(&path, bytes)
+        .await
+        .map_err(|source| GithubError::Io { path, source })
+}
+
+/// Body of the recorded response to `url`, or the error of the request if it failed
+async fn read_capture(dir: &Path, url: &str) -> Result<Vec<u8>, GithubError> {
+    let path = capture_path(dir, url);
+    let not_recorded = |path: PathBuf| GithubError::NotRecorded {
+        url: url.to_string(),
+        path,
+    };
+    let entry = read(&path).await.map_err(|_| not_recorded(path.clone()))?;
+    let entry = serde_json::from_slice::<CaptureEntry>(&entry)?;
+    if !StatusCode::from_u16(entry.status).is_ok_and(|o| o.is_success()) {
+        return Err(GithubError::Status {
+            url: url.to_string(),
+            status: entry.status,
+        });
+    }
+    let path = path.with_extension(BODY_EXTENSION);
+    read(&path).await.map_err(|_| not_recorded(path))
+}
+
+/// Copies the recorded `asset` to `path` as if it was downloaded
+async fn replay_asset(
+    dir: &Path,
+    asset: &Asset,
+    path: PathBuf,
+    reporter: &dyn Reporter,
+) -> Result<DownloadedAsset, GithubError> {
+    let bytes = read_capture(dir, &asset.browser_download_url).await?;
+    let downloaded = bytes.len() as u64;
+    if downloaded != asset.size {
+        return Err(GithubError::SizeMismatch {
+            name: asset.name.clone(),
+            downloaded,
+            expected: asset.size,
+        });
     }
+    write(&path, &bytes)
+        .await
+        .map_err(|source| GithubError::Io {
+            path: path.clone(),
+            source,
+        })?;
+    reporter.download_progress(downloaded);
+    reporter.download_finished(&path);
+    Ok(DownloadedAsset {
+        path,
+        sha256: hex::encode(Sha256::digest(&bytes)),
+    })
 }
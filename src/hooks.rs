@@ -0,0 +1,109 @@
+use crate::domain::{Repository, ToolVersion};
+use std::{
+    path::Path,
+    process::{ExitStatus, Stdio},
+};
+use tokio::process::Command;
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum HookError {
+    #[error("Failed to execute hook: {command:?}.")]
+    Spawn {
+        command: String,
+        source: std::io::Error,
+    },
+    #[error("Hook {command:?} failed ({status}): {stderr}")]
+    Failed {
+        command: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+}
+
+/// Information about an install exposed to hooks as environment variables
+#[derive(Clone, Copy, Debug)]
+pub struct HookContext<'a> {
+    pub repo: &'a Repository,
+    pub old_version: Option<&'a ToolVersion>,
+    pub new_version: &'a ToolVersion,
+    pub install_path: &'a Path,
+}
+
+impl HookContext<'_> {
+    fn envs(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("DL_RELEASES_REPO", self.repo.to_string()),
+            ("DL_RELEASES_BINARY", self.repo.repository.clone()),
+            (
+                "DL_RELEASES_OLD_VERSION",
+                self.old_version.map(|o| o.to_string()).unwrap_or_default(),
+            ),
+            ("DL_RELEASES_NEW_VERSION", self.new_version.to_string()),
+            (
+                "DL_RELEASES_INSTALL_PATH",
+                self.install_path.to_string_lossy().into_owned(),
+            ),
+        ]
+    }
+}
+
+/// Runs each command with `sh -c` in order, stopping at the first failure
+pub async fn run_hooks(commands: &[String], ctx: &HookContext<'_>) -> Result<(), HookError> {
+    for command in commands {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .envs(ctx.envs())
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .map_err(|source| HookError::Spawn {
+                command: command.clone(),
+                source,
+            })?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(HookError::Failed {
+                command: command.clone(),
+                status: output.status,
+                stderr: stderr.trim().to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use googletest::prelude::*;
+    use std::{fs::read_to_string, str::FromStr};
+    use tempfile::tempdir;
+
+    #[gtest]
+    #[tokio::test]
+    async fn run_hooks_works() {
+        let dir = tempdir().unwrap();
+        let out = dir.path().join("out.txt");
+        let repo = Repository::from_str("user/tool").unwrap();
+        let old_version = ToolVersion::from_str("1.0.0").unwrap();
+        let new_version = ToolVersion::from_str("1.1.0").unwrap();
+        let install_path = dir.path().join("tool");
+        let ctx = HookContext {
+            repo: &repo,
+            old_version: Some(&old_version),
+            new_version: &new_version,
+            install_path: &install_path,
+        };
+        let commands = [format!(
+            "echo $DL_RELEASES_REPO $DL_RELEASES_OLD_VERSION $DL_RELEASES_NEW_VERSION > {out:?}"
+        )];
+        expect_that!(run_hooks(&commands, &ctx).await, ok(()));
+        expect_that!(read_to_string(&out), ok(eq("user/tool 1.0.0 1.1.0\n")));
+        let commands = ["echo oops >&2; exit 3".to_string(), format!("rm {out:?}")];
+        let res = run_hooks(&commands, &ctx).await;
+        expect_that!(res, err(displays_as(contains_substring("oops"))));
+        expect_that!(out.exists(), eq(true));
+    }
+}
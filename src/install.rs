@@ -0,0 +1,363 @@
+use crate::domain::{Repository, ToolVersion};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsStr,
+    fs,
+    os::unix::fs::symlink,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tempfile::TempDir;
+
+/// Directory inside the data path keeping the binaries replaced by updates
+const BACKUPS_DIR: &str = "backups";
+/// Directory inside the data path keeping the releases of the versioned layout
+const VERSIONS_DIR: &str = "versions";
+
+/// How binaries are laid out on disk
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InstallLayout {
+    /// Binaries are placed directly in the binaries location
+    #[default]
+    Flat,
+    /// Each release is kept in `<data_path>/versions/<user>/<repo>/<version>/` and
+    /// the binaries location holds a symlink to the active version
+    Versioned,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum InstallError {
+    #[error("Invalid path: {0:?}.")]
+    InvalidPath(PathBuf),
+    #[error("Failed to create a temporary file in {dir:?}.")]
+    Temp {
+        dir: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to create directory {path:?}.")]
+    CreateDir {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to read directory {path:?}.")]
+    ReadDir {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to remove {path:?}.")]
+    Remove {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to copy {from:?} to {to:?}.")]
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to move {from:?} to {to:?}.")]
+    Move {
+        from: PathBuf,
+        to: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to create symlink to {path:?}.")]
+    Symlink {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("No backup found for {0:?}.")]
+    NoBackup(PathBuf),
+    #[error("Version {version} is not installed ({path:?} not found).")]
+    VersionNotInstalled { version: ToolVersion, path: PathBuf },
+}
+
+/// Creates a temporary directory next to the binaries so staged files can be moved
+/// into place atomically. The directory is removed when dropped.
+pub fn staging_dir(binaries_location: impl AsRef<Path>) -> Result<TempDir, InstallError> {
+    let dir = binaries_location.as_ref();
+    tempfile::Builder::new()
+        .prefix(".dl-releases-")
+        .tempdir_in(dir)
+        .map_err(|source| InstallError::Temp {
+            dir: dir.to_owned(),
+            source,
+        })
+}
+
+fn file_name(path: &Path) -> Result<&OsStr, InstallError> {
+    path.file_name()
+        .ok_or_else(|| InstallError::InvalidPath(path.to_owned()))
+}
+
+fn parent(path: &Path) -> Result<&Path, InstallError> {
+    path.parent()
+        .ok_or_else(|| InstallError::InvalidPath(path.to_owned()))
+}
+
+fn rename(from: &Path, to: &Path) -> Result<(), InstallError> {
+    fs::rename(from, to).map_err(|source| InstallError::Move {
+        from: from.to_owned(),
+        to: to.to_owned(),
+        source,
+    })
+}
+
+fn copy(from: &Path, to: &Path) -> Result<(), InstallError> {
+    fs::copy(from, to)
+        .map(|_| ())
+        .map_err(|source| InstallError::Copy {
+            from: from.to_owned(),
+            to: to.to_owned(),
+            source,
+        })
+}
+
+fn create_dir_all(path: &Path) -> Result<(), InstallError> {
+    fs::create_dir_all(path).map_err(|source| InstallError::CreateDir {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+fn backup_path(target: &Path, backup_dir: &Path) -> Result<PathBuf, InstallError> {
+    Ok(backup_dir.join(file_name(target)?))
+}
+
+/// Copies `src` into `dst` going through a temporary file in the same directory,
+/// so `dst` is never left half written.
+fn copy_atomic(src: &Path, dst: &Path) -> Result<(), InstallError> {
+    let dir = parent(dst)?;
+    let tmp_path = tempfile::Builder::new()
+        .prefix(".dl-releases-")
+        .tempfile_in(dir)
+        .map_err(|source| InstallError::Temp {
+            dir: dir.to_owned(),
+            source,
+        })?
+        .into_temp_path();
+    copy(src, &tmp_path)?;
+    let from = tmp_path.to_path_buf();
+    tmp_path.persist(dst).map_err(|e| InstallError::Move {
+        from,
+        to: dst.to_owned(),
+        source: e.error,
+    })?;
+    Ok(())
+}
+
+/// Moves the `staged` binary to `target`, keeping a copy of the previous binary (if
+/// any) in `backup_dir`.
+pub fn install_binary(
+    staged: impl AsRef<Path>,
+    target: impl AsRef<Path>,
+    backup_dir: impl AsRef<Path>,
+) -> Result<(), InstallError> {
+    let staged = staged.as_ref();
+    let target = target.as_ref();
+    if target.exists() {
+        backup_binary(target, backup_dir)?;
+    }
+    rename(staged, target)
+}
+
+/// Keeps a copy of `target` in `backup_dir`, restored by [`rollback_binary`]
+pub fn backup_binary(
+    target: impl AsRef<Path>,
+    backup_dir: impl AsRef<Path>,
+) -> Result<(), InstallError> {
+    let target = target.as_ref();
+    let backup_dir = backup_dir.as_ref();
+    create_dir_all(backup_dir)?;
+    copy_atomic(target, &backup_path(target, backup_dir)?)
+}
+
+/// Restores the backup of `target` from `backup_dir`. The replaced binary becomes
+/// the new backup, so rolling back twice returns to the original state.
+pub fn rollback_binary(
+    target: impl AsRef<Path>,
+    backup_dir: impl AsRef<Path>,
+) -> Result<(), InstallError> {
+    let target = target.as_ref();
+    let backup = backup_path(target, backup_dir.as_ref())?;
+    if !backup.exists() {
+        return Err(InstallError::NoBackup(target.to_owned()));
+    }
+    let staging = staging_dir(parent(target)?)?;
+    let staged = staging.path().join(file_name(&backup)?);
+    copy(&backup, &staged)?;
+    install_binary(&staged, target, backup_dir)
+}
+
+/// Directory keeping the binaries replaced by updates of the flat layout
+pub fn backups_dir(data_path: impl AsRef<Path>) -> PathBuf {
+    data_path.as_ref().join(BACKUPS_DIR)
+}
+
+/// Directory keeping every installed version of `repo`
+pub fn versions_dir(data_path: impl AsRef<Path>, repo: &Repository) -> PathBuf {
+    data_path
+        .as_ref()
+        .join(VERSIONS_DIR)
+        .join(&repo.user)
+        .join(&repo.repository)
+}
+
+/// Moves the `staged` binary into `<versions_dir>/<version>/` and returns its new path
+pub fn install_version(
+    staged: impl AsRef<Path>,
+    versions_dir: impl AsRef<Path>,
+    version: &ToolVersion,
+) -> Result<PathBuf, InstallError> {
+    let staged = staged.as_ref();
+    let dir = versions_dir.as_ref().join(version.dir_name());
+    create_dir_all(&dir)?;
+    let path = dir.join(file_name(staged)?);
+    rename(staged, &path)?;
+    Ok(path)
+}
+
+/// Points the `target` symlink to `version`, replacing whatever `target` was atomically
+pub fn activate_version(
+    versions_dir: impl AsRef<Path>,
+    version: &ToolVersion,
+    target: impl AsRef<Path>,
+) -> Result<(), InstallError> {
+    let target = target.as_ref();
+    let fname = file_name(target)?;
+    let src = versions_dir.as_ref().join(version.dir_name()).join(fname);
+    if !src.exists() {
+        return Err(InstallError::VersionNotInstalled {
+            version: version.clone(),
+            path: src,
+        });
+    }
+    let staging = staging_dir(parent(target)?)?;
+    let link = staging.path().join(fname);
+    symlink(&src, &link).map_err(|source| InstallError::Symlink {
+        path: src.clone(),
+        source,
+    })?;
+    rename(&link, target)
+}
+
+/// Lists the installed versions, sorted from oldest to newest
+pub fn list_versions(versions_dir: impl AsRef<Path>) -> Result<Vec<ToolVersion>, InstallError> {
+    let versions_dir = versions_dir.as_ref();
+    if !versions_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut versions = fs::read_dir(versions_dir)
+        .map_err(|source| InstallError::ReadDir {
+            path: versions_dir.to_owned(),
+            source,
+        })?
+        .filter_map(|o| {
+            let entry = o.ok()?;
+            entry.file_type().ok()?.is_dir().then_some(())?;
+            ToolVersion::from_str(entry.file_name().to_str()?).ok()
+        })
+        .collect::<Vec<_>>();
+    versions.sort();
+    Ok(versions)
+}
+
+/// Version the `target` symlink currently points to, if it points into `versions_dir`
+pub fn active_version(
+    versions_dir: impl AsRef<Path>,
+    target: impl AsRef<Path>,
+) -> Option<ToolVersion> {
+    let link = fs::read_link(target).ok()?;
+    let version_dir = link.parent()?;
+    if version_dir.parent()? != versions_dir.as_ref() {
+        return None;
+    }
+    ToolVersion::from_str(version_dir.file_name()?.to_str()?).ok()
+}
+
+/// Deletes old versions keeping the newest `keep` ones and the active one. Returns the
+/// removed versions.
+pub fn prune_versions(
+    versions_dir: impl AsRef<Path>,
+    target: impl AsRef<Path>,
+    keep: usize,
+) -> Result<Vec<ToolVersion>, InstallError> {
+    let versions_dir = versions_dir.as_ref();
+    let active = active_version(versions_dir, target);
+    let mut versions = list_versions(versions_dir)?;
+    versions.truncate(versions.len().saturating_sub(keep));
+    versions.retain(|o| Some(o) != active.as_ref());
+    for version in &versions {
+        let dir = versions_dir.join(version.dir_name());
+        fs::remove_dir_all(&dir).map_err(|source| InstallError::Remove { path: dir, source })?;
+    }
+    Ok(versions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use googletest::prelude::*;
+    use std::fs::{read_to_string, write};
+    use tempfile::tempdir;
+
+    #[gtest]
+    fn install_and_rollback_works() {
+        let bin_dir = tempdir().unwrap();
+        let backup_dir = tempdir().unwrap();
+        let target = bin_dir.path().join("tool");
+        for content in ["v1", "v2"] {
+            let staging = staging_dir(&bin_dir).unwrap();
+            let staged = staging.path().join("tool");
+            write(&staged, content).unwrap();
+            install_binary(&staged, &target, &backup_dir).unwrap();
+        }
+        expect_that!(read_to_string(&target), ok(eq("v2")));
+        rollback_binary(&target, &backup_dir).unwrap();
+        expect_that!(read_to_string(&target), ok(eq("v1")));
+        rollback_binary(&target, &backup_dir).unwrap();
+        expect_that!(read_to_string(&target), ok(eq("v2")));
+        // Only the target should remain, staging directories are cleaned up
+        expect_that!(fs::read_dir(&bin_dir).unwrap().count(), eq(1));
+    }
+
+    #[gtest]
+    fn versioned_layout_works() {
+        let bin_dir = tempdir().unwrap();
+        let data_dir = tempdir().unwrap();
+        let target = bin_dir.path().join("tool");
+        let repo = Repository::from_str("user/tool").unwrap();
+        let versions = versions_dir(&data_dir, &repo);
+        let [v1, v2, v3] = ["0.1.0", "0.2.0", "0.10.0"].map(|o| ToolVersion::from_str(o).unwrap());
+        for version in [&v1, &v2, &v3] {
+            fs::create_dir_all(&versions).unwrap();
+            let staging = staging_dir(&versions).unwrap();
+            let staged = staging.path().join("tool");
+            write(&staged, version.to_string()).unwrap();
+            install_version(&staged, &versions, version).unwrap();
+            activate_version(&versions, version, &target).unwrap();
+        }
+        expect_that!(read_to_string(&target), ok(eq("0.10.0")));
+        expect_that!(
+            list_versions(&versions),
+            ok(eq(&[v1.clone(), v2.clone(), v3.clone()]))
+        );
+        activate_version(&versions, &v1, &target).unwrap();
+        expect_that!(read_to_string(&target), ok(eq("0.1.0")));
+        expect_that!(active_version(&versions, &target), some(eq(&v1)));
+        // The active version is always kept
+        expect_that!(prune_versions(&versions, &target, 1), ok(eq(&[v2])));
+        expect_that!(list_versions(&versions), ok(eq(&[v1, v3])));
+        expect_that!(fs::read_dir(&bin_dir).unwrap().count(), eq(1));
+    }
+
+    #[gtest]
+    fn rollback_without_backup_fails() {
+        let bin_dir = tempdir().unwrap();
+        let backup_dir = tempdir().unwrap();
+        let res = rollback_binary(bin_dir.path().join("tool"), &backup_dir);
+        expect_that!(res, err(pat!(InstallError::NoBackup(_))));
+    }
+}
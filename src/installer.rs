@@ -0,0 +1,869 @@
+use crate::{
+    cache::{Cache, CacheError},
+    config::{ConfigError, Configuration, RepoConfig},
+    domain::{
+        Asset, ChecksumError, ChecksumPolicy, FindAssetError, Release, Repository, SignatureError,
+        ToolVersion, VersionError, verify_checksum,
+    },
+    github_client::{Capture, DownloadedAsset, GithubClient, GithubError},
+    hooks::{HookContext, HookError, run_hooks},
+    install::{
+        InstallError, InstallLayout, activate_version, backup_binary, backups_dir, install_binary,
+        install_version, rollback_binary, staging_dir, versions_dir,
+    },
+    lockfile::{LockedRelease, Lockfile},
+    manifest::{InstalledTool, Manifest, ManifestError},
+    reporter::{NoopReporter, Reporter},
+    utils::{
+        ExtractError, GetVersionError, VerifyError, extract_file_async, find_binary,
+        get_version_with, verify_signature_async,
+    },
+};
+use futures::{StreamExt, stream};
+use jiff::Timestamp;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tokio::fs::{create_dir_all, remove_dir_all, remove_file};
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum InstallerError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Manifest(#[from] ManifestError),
+    #[error("Failed to get the installed version.")]
+    InstalledVersion(#[source] VersionError),
+    #[error(transparent)]
+    GetVersion(#[from] GetVersionError),
+    #[error("Failed to get release {tag:?}.")]
+    Release { tag: String, source: GithubError },
+    #[error("Failed to get latest release.")]
+    LatestRelease(#[source] GithubError),
+    #[error(transparent)]
+    ReleaseVersion(#[from] VersionError),
+    #[error(transparent)]
+    FindAsset(#[from] FindAssetError),
+    #[error("Failed to find checksum.")]
+    FindChecksum(#[source] GithubError),
+    #[error(transparent)]
+    Checksum(#[from] ChecksumError),
+    #[error(transparent)]
+    Signature(#[from] SignatureError),
+    #[error("Failed to download signature.")]
+    DownloadSignature(#[source] GithubError),
+    #[error(transparent)]
+    Verify(#[from] VerifyError),
+    #[error("Failed to download {0:?}.")]
+    Download(String, #[source] GithubError),
+    #[error("Failed to create directory {path:?}.")]
+    CreateDir {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to remove {path:?}.")]
+    Remove {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error(transparent)]
+    Extract(#[from] ExtractError),
+    #[error("Extracted version ({extracted}) doesn't match the release one ({expected}).")]
+    VersionMismatch {
+        expected: ToolVersion,
+        extracted: ToolVersion,
+    },
+    #[error("pre_install hook failed.")]
+    PreInstallHook(#[source] HookError),
+    #[error("post_install hook failed, {}.", rollback_status(.rollback))]
+    PostInstallHook {
+        source: HookError,
+        /// Error undoing the update, if any
+        rollback: Option<Box<InstallerError>>,
+    },
+    #[error(transparent)]
+    Install(#[from] InstallError),
+    #[error(transparent)]
+    Cache(#[from] CacheError),
+    #[error("{0} is not cached, it should be installed online first.")]
+    NotCached(String),
+    #[error("Failed to read {path:?}.")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("{0} is not in the lockfile.")]
+    NotLocked(String),
+    #[error("Release {tag:?} has no asset {asset:?} at {url}, the lockfile should be updated.")]
+    LockedAsset {
+        tag: String,
+        asset: String,
+        url: String,
+    },
+}
+
+/// Creates the reporter receiving the progress of each repository
+pub type ReporterFactory = Box<dyn Fn(&Repository) -> Box<dyn Reporter> + Send + Sync>;
+
+/// Checks, downloads and installs the releases of repositories
+pub struct Installer {
+    client: GithubClient,
+    download_dir: PathBuf,
+    binaries_dir: PathBuf,
+    cache: Cache,
+    reporter: ReporterFactory,
+    keep_downloads: bool,
+    checksum: ChecksumPolicy,
+    jobs: u16,
+    offline: bool,
+    lockfile: Option<Lockfile>,
+}
+
+pub struct InstallerBuilder {
+    installer: Installer,
+}
+
+/// What [`Installer::install`] would do for a repository
+#[derive(Clone, Debug)]
+pub struct Plan {
+    /// Version currently installed, if any
+    pub current_version: Option<ToolVersion>,
+    /// Release to install, the latest one or the pinned one
+    pub release: Release,
+    pub release_version: ToolVersion,
+    pinned: bool,
+}
+
+/// Result of installing a repository
+#[derive(Clone, Debug)]
+pub enum Outcome {
+    /// Nothing was installed, the current version is the wanted one
+    UpToDate(ToolVersion),
+    Installed(Update),
+}
+
+/// A new release installed by the [`Installer`]
+#[derive(Clone, Debug)]
+pub struct Update {
+    pub tool: InstalledTool,
+    pub previous_version: Option<ToolVersion>,
+    /// Path of the installed binary
+    pub path: PathBuf,
+}
+
+/// Outcome of a repository in [`Installer::update_all`]
+#[derive(Debug)]
+pub struct RepoOutcome {
+    pub repo: Repository,
+    pub config: RepoConfig,
+    pub result: Result<Outcome, InstallerError>,
+}
+
+impl Plan {
+    /// Whether the current version is the wanted one, pinned repos can be downgraded
+    pub fn is_up_to_date(&self) -> bool {
+        match &self.current_version {
+            Some(o) if self.pinned => o == &self.release_version,
+            Some(o) => o >= &self.release_version,
+            None => false,
+        }
+    }
+}
+
+impl Outcome {
+    pub fn update(&self) -> Option<&Update> {
+        match self {
+            Outcome::UpToDate(_) => None,
+            Outcome::Installed(o) => Some(o),
+        }
+    }
+}
+
+impl InstallerBuilder {
+    pub fn reporter(
+        mut self,
+        f: impl Fn(&Repository) -> Box<dyn Reporter> + Send + Sync + 'static,
+    ) -> Self {
+        self.installer.reporter = Box::new(f);
+        self
+    }
+
+    /// Whether downloaded assets are kept in the cache after extracting them, true by
+    /// default. Assets cached by earlier installs are always kept.
+    pub fn keep_downloads(mut self, keep_downloads: bool) -> Self {
+        self.installer.keep_downloads = keep_downloads;
+        self
+    }
+
+    /// Checksum policy of repositories without one
+    pub fn checksum(mut self, checksum: ChecksumPolicy) -> Self {
+        self.installer.checksum = checksum;
+        self
+    }
+
+    /// Maximum number of repositories updated concurrently by [`Installer::update_all`]
+    pub fn jobs(mut self, jobs: u16) -> Self {
+        self.installer.jobs = jobs.max(1);
+        self
+    }
+
+    /// Whether releases are resolved from the cached release metadata and assets
+    /// installed from the cache, without doing any request
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.installer.offline = offline;
+        self
+    }
+
+    /// Installs the releases recorded in `lockfile` instead of resolving them,
+    /// repositories missing from it fail
+    pub fn lockfile(mut self, lockfile: Lockfile) -> Self {
+        self.installer.lockfile = Some(lockfile);
+        self
+    }
+
+    pub fn build(self) -> Installer {
+        self.installer
+    }
+}
+
+impl Installer {
+    /// Assets are downloaded to `download_dir`, which also keeps the manifest and the
+    /// versioned installs, and binaries are installed into `binaries_dir`
+    pub fn builder(
+        client: GithubClient,
+        download_dir: impl Into<PathBuf>,
+        binaries_dir: impl Into<PathBuf>,
+    ) -> InstallerBuilder {
+        let download_dir = download_dir.into();
+        InstallerBuilder {
+            installer: Installer {
+                client,
+                cache: Cache::new(&download_dir),
+                download_dir,
+                binaries_dir: binaries_dir.into(),
+                reporter: Box::new(|_| Box::new(NoopReporter)),
+                keep_downloads: true,
+                checksum: ChecksumPolicy::default(),
+                jobs: 4,
+                offline: false,
+                lockfile: None,
+            },
+        }
+    }
+
+    pub fn client(&self) -> &GithubClient {
+        &self.client
+    }
+
+    /// Finds the installed version and the release to install
+    pub async fn plan(
+        &self,
+        repo: &Repository,
+        repo_config: &RepoConfig,
+    ) -> Result<Plan, InstallerError> {
+        let manifest = Manifest::load(&self.download_dir).await?;
+        self.plan_with(repo, repo_config, &manifest).await
+    }
+
+    /// Installs the release of `repo` if it's not already installed and records it in
+    /// the manifest
+    pub async fn install(
+        &self,
+        repo: &Repository,
+        repo_config: &RepoConfig,
+    ) -> Result<Outcome, InstallerError> {
+        let mut manifest = Manifest::load(&self.download_dir).await?;
+        let outcome = self.install_with(repo, repo_config, &manifest).await?;
+        if let Outcome::Installed(update) = &outcome {
+            manifest.insert(repo.to_string(), update.tool.clone());
+            manifest.save(&self.download_dir).await?;
+        }
+        Ok(outcome)
+    }
+
+    /// Resolves the release of `repo` to record in a lockfile, following its pin and
+    /// pattern but not the current lockfile
+    pub async fn lock(
+        &self,
+        repo: &Repository,
+        repo_config: &RepoConfig,
+    ) -> Result<LockedRelease, InstallerError> {
+        let release = self
+            .resolve_release(repo, repo_config.pin.as_deref())
+            .await?;
+        let asset = release.find_asset(&repo_config.pat)?;
+        let sha256 = if self.offline {
+            asset.sha256()
+        } else {
+            let reporter = (self.reporter)(repo);
+            let policy = repo_config.checksum.unwrap_or(self.checksum);
+            self.find_checksum(&release, asset, policy, reporter.as_ref())
+                .await?
+        };
+        Ok(LockedRelease {
+            tag: release.tag_name.clone(),
+            asset: asset.name.clone(),
+            url: asset.browser_download_url.clone(),
+            sha256,
+        })
+    }
+
+    /// Installs `archive`, a copy of a release asset of `repo`, without doing any
+    /// request and records it in the manifest. The archive is checked against the
+    /// cached release metadata when one of its assets has the same name.
+    pub async fn install_file(
+        &self,
+        repo: &Repository,
+        repo_config: &RepoConfig,
+        archive: &Path,
+    ) -> Result<Update, InstallerError> {
+        let reporter = (self.reporter)(repo);
+        let reporter = reporter.as_ref();
+        let mut manifest = Manifest::load(&self.download_dir).await?;
+        let current_version = self.current_version(repo, repo_config, &manifest).await?;
+        let name = archive
+            .file_name()
+            .map(|o| o.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let cached = self.cache.releases(repo).into_iter().find_map(|release| {
+            let asset = release.assets.iter().find(|o| o.name == name)?.clone();
+            Some((release, asset))
+        });
+        let release_version = cached
+            .as_ref()
+            .map(|(release, _)| release.version(repo_config.version_scheme))
+            .transpose()?;
+        let checksum_policy = repo_config.checksum.unwrap_or(self.checksum);
+        if checksum_policy != ChecksumPolicy::Off {
+            let data = tokio::fs::read(archive)
+                .await
+                .map_err(|source| InstallerError::Read {
+                    path: archive.to_owned(),
+                    source,
+                })?;
+            let expected = cached.as_ref().and_then(|(_, asset)| asset.sha256());
+            let warning = verify_checksum(
+                checksum_policy,
+                &name,
+                expected.as_deref(),
+                &hex::encode(Sha256::digest(&data)),
+            )?;
+            if let Some(warning) = warning {
+                reporter.warning(&warning);
+            }
+        }
+        let (version, path) = self
+            .install_archive(
+                repo,
+                repo_config,
+                reporter,
+                archive,
+                release_version.as_ref(),
+                current_version.as_ref(),
+            )
+            .await?;
+        let update = Update {
+            tool: InstalledTool {
+                version: version.to_string(),
+                tag: cached
+                    .map(|(release, _)| release.tag_name)
+                    .unwrap_or_default(),
+                asset: name,
+                installed_at: Timestamp::now(),
+            },
+            previous_version: current_version,
+            path,
+        };
+        manifest.insert(repo.to_string(), update.tool.clone());
+        manifest.save(&self.download_dir).await?;
+        Ok(update)
+    }
+
+    /// Installs every repository of `config` concurrently. Failures of a repository
+    /// don't stop the others, only failing to read the config or the manifest does.
+    pub async fn update_all(
+        &self,
+        config: Configuration,
+    ) -> Result<Vec<RepoOutcome>, InstallerError> {
+        let repos = config.read_repositories()?;
+        let mut manifest = Manifest::load(&self.download_dir).await?;
+        let manifest_ref = &manifest;
+        let outcomes = stream::iter(repos)
+            .map(|(repo, config)| async move {
+                let result = self.install_with(&repo, &config, manifest_ref).await;
+                RepoOutcome {
+                    repo,
+                    config,
+                    result,
+                }
+            })
+            .buffer_unordered(self.jobs.into())
+            .collect::<Vec<_>>()
+            .await;
+        for outcome in &outcomes {
+            if let Ok(Outcome::Installed(update)) = &outcome.result {
+                manifest.insert(outcome.repo.to_string(), update.tool.clone());
+            }
+        }
+        manifest.save(&self.download_dir).await?;
+        Ok(outcomes)
+    }
+
+    async fn plan_with(
+        &self,
+        repo: &Repository,
+        repo_config: &RepoConfig,
+        manifest: &Manifest,
+    ) -> Result<Plan, InstallerError> {
+        let current_version = self.current_version(repo, repo_config, manifest).await?;
+        let pin = match self.locked(repo)? {
+            Some(locked) => Some(locked.tag.as_str()),
+            None => repo_config.pin.as_deref(),
+        };
+        let release = self.resolve_release(repo, pin).await?;
+        let release_version = release.version(repo_config.version_scheme)?;
+        Ok(Plan {
+            current_version,
+            release,
+            release_version,
+            pinned: pin.is_some(),
+        })
+    }
+
+    /// Entry of `repo` in the lockfile, when installing the locked releases
+    fn locked(&self, repo: &Repository) -> Result<Option<&LockedRelease>, InstallerError> {
+        match &self.lockfile {
+            Some(lockfile) => lockfile
+                .get(&repo.to_string())
+                .map(Some)
+                .ok_or_else(|| InstallerError::NotLocked(repo.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Release with the `pin` tag or the latest one, from the cached release metadata
+    /// when offline
+    async fn resolve_release(
+        &self,
+        repo: &Repository,
+        pin: Option<&str>,
+    ) -> Result<Release, InstallerError> {
+        if self.offline {
+            return self.cached_release(repo, pin);
+        }
+        let release = self.fetch_release(repo, pin).await?;
+        self.cache.insert_release(repo, &release).await?;
+        Ok(release)
+    }
+
+    /// Version of the installed binary, read from the manifest when the release
+    /// version is trusted
+    async fn current_version(
+        &self,
+        repo: &Repository,
+        repo_config: &RepoConfig,
+        manifest: &Manifest,
+    ) -> Result<Option<ToolVersion>, InstallerError> {
+        if repo_config.trust_release_version {
+            return manifest
+                .get(&repo.to_string())
+                .map(InstalledTool::version)
+                .transpose()
+                .map_err(InstallerError::InstalledVersion);
+        }
+        let probe = repo_config.version_probe()?;
+        match find_binary(&repo.repository) {
+            Some(path) => Ok(Some(get_version_with(path, &probe).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn fetch_release(
+        &self,
+        repo: &Repository,
+        pin: Option<&str>,
+    ) -> Result<Release, InstallerError> {
+        match pin {
+            Some(tag) => self
+                .client
+                .get_release_by_tag(repo, tag)
+                .await
+                .map_err(|source| InstallerError::Release {
+                    tag: tag.to_owned(),
+                    source,
+                }),
+            None => self
+                .client
+                .get_latest_release(repo)
+                .await
+                .map_err(InstallerError::LatestRelease),
+        }
+    }
+
+    /// Pinned release or latest non prerelease among the cached release metadata
+    fn cached_release(
+        &self,
+        repo: &Repository,
+        pin: Option<&str>,
+    ) -> Result<Release, InstallerError> {
+        let mut releases = self.cache.releases(repo).into_iter();
+        match pin {
+            Some(tag) => releases
+                .find(|o| o.tag_name == tag)
+                .ok_or_else(|| InstallerError::NotCached(format!("Release {tag:?} of {repo}"))),
+            None => releases
+                .find(|o| !o.prerelease)
+                .ok_or_else(|| InstallerError::NotCached(format!("Latest release of {repo}"))),
+        }
+    }
+
+    async fn install_with(
+        &self,
+        repo: &Repository,
+        repo_config: &RepoConfig,
+        manifest: &Manifest,
+    ) -> Result<Outcome, InstallerError> {
+        let reporter = (self.reporter)(repo);
+        let reporter = reporter.as_ref();
+        let plan = self.plan_with(repo, repo_config, manifest).await?;
+        if plan.is_up_to_date() {
+            let version = plan.release_version;
+            let version = plan.current_version.unwrap_or(version);
+            reporter.up_to_date(&version);
+            return Ok(Outcome::UpToDate(version));
+        }
+        let Plan {
+            current_version,
+            release,
+            release_version,
+            ..
+        } = plan;
+        let locked = self.locked(repo)?;
+        let asset = match locked {
+            Some(locked) => release
+                .assets
+                .iter()
+                .find(|o| o.name == locked.asset && o.browser_download_url == locked.url)
+                .ok_or_else(|| InstallerError::LockedAsset {
+                    tag: release.tag_name.clone(),
+                    asset: locked.asset.clone(),
+                    url: locked.url.clone(),
+                })?,
+            None => release.find_asset(&repo_config.pat)?,
+        };
+        let locked_sha256 = locked.and_then(|o| o.sha256.as_deref());
+        let (downloaded, is_cached) = self
+            .download(&release, asset, repo_config, locked_sha256, reporter)
+            .await?;
+        let result = self
+            .install_archive(
+                repo,
+                repo_config,
+                reporter,
+                &downloaded,
+                Some(&release_version),
+                current_version.as_ref(),
+            )
+            .await;
+        // Assets cached by earlier installs are kept
+        if !self.keep_downloads && !is_cached {
+            let dir = downloaded.parent().unwrap_or(&downloaded);
+            remove_dir_all(dir)
+                .await
+                .map_err(|source| InstallerError::Remove {
+                    path: dir.to_owned(),
+                    source,
+                })?;
+        }
+        let (version, path) = result?;
+        let tool = InstalledTool {
+            version: version.to_string(),
+            tag: release.tag_name.clone(),
+            asset: asset.name.clone(),
+            installed_at: Timestamp::now(),
+        };
+        Ok(Outcome::Installed(Update {
+            tool,
+            previous_version: current_version,
+            path,
+        }))
+    }
+
+    /// Extracts the binary of `archive`, checks its version against `expected_version`
+    /// when given and installs it running the hooks, returns its version and path
+    async fn install_archive(
+        &self,
+        repo: &Repository,
+        repo_config: &RepoConfig,
+        reporter: &dyn Reporter,
+        archive: &Path,
+        expected_version: Option<&ToolVersion>,
+        current_version: Option<&ToolVersion>,
+    ) -> Result<(ToolVersion, PathBuf), InstallerError> {
+        let versions = versions_dir(&self.download_dir, repo);
+        let staging = match repo_config.layout {
+            InstallLayout::Flat => staging_dir(&self.binaries_dir)?,
+            InstallLayout::Versioned => {
+                create_dir_all(&versions)
+                    .await
+                    .map_err(|source| InstallerError::CreateDir {
+                        path: versions.clone(),
+                        source,
+                    })?;
+                staging_dir(&versions)?
+            }
+        };
+        let staged_path =
+            extract_file_async(archive, &repo.repository, staging.path(), reporter).await?;
+        let extracted_version = match expected_version {
+            Some(version) if repo_config.trust_release_version => version.clone(),
+            _ => get_version_with(&staged_path, &repo_config.version_probe()?).await?,
+        };
+        if let Some(expected) = expected_version
+            && &extracted_version != expected
+        {
+            return Err(InstallerError::VersionMismatch {
+                expected: expected.clone(),
+                extracted: extracted_version,
+            });
+        }
+        let target = self.binaries_dir.join(&repo.repository);
+        let hook_ctx = HookContext {
+            repo,
+            old_version: current_version,
+            new_version: &extracted_version,
+            install_path: &target,
+        };
+        if !repo_config.pre_install.is_empty() {
+            reporter.running_hooks("pre_install");
+            run_hooks(&repo_config.pre_install, &hook_ctx)
+                .await
+                .map_err(InstallerError::PreInstallHook)?;
+        }
+        let backups = backups_dir(&self.download_dir);
+        let replaced = target.exists();
+        // Eg: installed with the flat layout before switching to the versioned one
+        let replaced_binary = target.symlink_metadata().is_ok_and(|o| o.is_file());
+        match repo_config.layout {
+            InstallLayout::Flat => install_binary(&staged_path, &target, &backups)?,
+            InstallLayout::Versioned => {
+                install_version(&staged_path, &versions, &extracted_version)?;
+                if replaced_binary {
+                    backup_binary(&target, &backups)?;
+                }
+                activate_version(&versions, &extracted_version, &target)?;
+            }
+        }
+        if !repo_config.post_install.is_empty() {
+            reporter.running_hooks("post_install");
+            if let Err(e) = run_hooks(&repo_config.post_install, &hook_ctx).await {
+                if !repo_config.rollback_on_hook_failure {
+                    reporter.warning(&format!("post_install hook failed: {e}"));
+                } else {
+                    let rollback = undo_install(
+                        repo_config.layout,
+                        &target,
+                        replaced,
+                        replaced_binary,
+                        &backups,
+                        &versions,
+                        current_version,
+                    )
+                    .await;
+                    return Err(InstallerError::PostInstallHook {
+                        source: e,
+                        rollback: rollback.err().map(Box::new),
+                    });
+                }
+            }
+        }
+        reporter.installed(&extracted_version, &target);
+        Ok((extracted_version, target))
+    }
+
+    /// Downloads `asset` into the cache, or reuses the cached copy, verifying its
+    /// checksum, against `locked_sha256` when given, and signature, returns its path
+    /// and whether it was already cached
+    async fn download(
+        &self,
+        release: &Release,
+        asset: &Asset,
+        repo_config: &RepoConfig,
+        locked_sha256: Option<&str>,
+        reporter: &dyn Reporter,
+    ) -> Result<(PathBuf, bool), InstallerError> {
+        if self.offline {
+            // Cached assets were verified when they were added
+            let expected = locked_sha256.map(str::to_owned).or_else(|| asset.sha256());
+            let downloaded = self
+                .cache
+                .get(asset, expected.as_deref())
+                .await
+                .ok_or_else(|| InstallerError::NotCached(format!("Asset {:?}", asset.name)))?;
+            reporter.cached(&downloaded.path);
+            return Ok((downloaded.path, true));
+        }
+        let (checksum_policy, expected_checksum) = match locked_sha256 {
+            // Locked digests are always verified
+            Some(sha256) => (ChecksumPolicy::Require, Some(sha256.to_owned())),
+            None => match repo_config.checksum.unwrap_or(self.checksum) {
+                ChecksumPolicy::Off => (ChecksumPolicy::Off, None),
+                policy => {
+                    let expected = self.find_checksum(release, asset, policy, reporter).await?;
+                    (policy, expected)
+                }
+            },
+        };
+        let signature = match &repo_config.signature {
+            Some(config) => {
+                let signature_asset = release
+                    .find_signature_asset(asset, config)
+                    .ok_or_else(|| SignatureError::Missing(asset.name.clone()))?;
+                let signature = self
+                    .client
+                    .get_asset_text(signature_asset)
+                    .await
+                    .map_err(InstallerError::DownloadSignature)?;
+                Some((config, signature))
+            }
+            None => None,
+        };
+        // Recordings should include the asset
+        let cached = match self.client.capture() {
+            Some(Capture::Record(_)) => None,
+            _ => self.cache.get(asset, expected_checksum.as_deref()).await,
+        };
+        let is_cached = cached.is_some();
+        let downloaded = match cached {
+            Some(downloaded) => {
+                reporter.cached(&downloaded.path);
+                downloaded
+            }
+            None => self.download_to_cache(asset, reporter).await?,
+        };
+        let warning = verify_checksum(
+            checksum_policy,
+            &asset.name,
+            expected_checksum.as_deref(),
+            &downloaded.sha256,
+        )?;
+        if let Some(warning) = warning {
+            reporter.warning(&warning);
+        }
+        if let Some((config, signature)) = signature {
+            verify_signature_async(&downloaded.path, config, signature, reporter).await?;
+        }
+        if self.keep_downloads && !is_cached {
+            self.cache.insert(asset, &downloaded).await?;
+        }
+        Ok((downloaded.path, is_cached))
+    }
+
+    /// Expected sha256 of `asset`, failing to find it is only an error under
+    /// [`ChecksumPolicy::Require`]
+    async fn find_checksum(
+        &self,
+        release: &Release,
+        asset: &Asset,
+        policy: ChecksumPolicy,
+        reporter: &dyn Reporter,
+    ) -> Result<Option<String>, InstallerError> {
+        match self.client.find_checksum(release, asset).await {
+            Ok(o) => Ok(o),
+            Err(e) if policy == ChecksumPolicy::Require => Err(InstallerError::FindChecksum(e)),
+            Err(e) => {
+                reporter.warning(&format!(
+                    "Failed to find the checksum of {:?}: {e}",
+                    asset.name
+                ));
+                Ok(None)
+            }
+        }
+    }
+
+    async fn download_to_cache(
+        &self,
+        asset: &Asset,
+        reporter: &dyn Reporter,
+    ) -> Result<DownloadedAsset, InstallerError> {
+        let dir = self.cache.entry_dir(asset);
+        create_dir_all(&dir)
+            .await
+            .map_err(|source| InstallerError::CreateDir {
+                path: dir.clone(),
+                source,
+            })?;
+        self.client
+            .download_asset(asset, &dir, reporter)
+            .await
+            .map_err(|e| InstallerError::Download(asset.name.clone(), e))
+    }
+}
+
+/// Restores the binary replaced by an install into `target`, or removes the new one
+/// if there was none, eg: the current version was found outside the binaries dir
+async fn undo_install(
+    layout: InstallLayout,
+    target: &Path,
+    replaced: bool,
+    replaced_binary: bool,
+    backups: &Path,
+    versions: &Path,
+    current_version: Option<&ToolVersion>,
+) -> Result<(), InstallerError> {
+    match (layout, current_version) {
+        // The replaced binary was backed up by `install_binary`
+        (InstallLayout::Flat, _) if replaced => Ok(rollback_binary(target, backups)?),
+        // The replaced binary was backed up before activating the new version
+        (InstallLayout::Versioned, _) if replaced_binary => Ok(rollback_binary(target, backups)?),
+        (InstallLayout::Versioned, Some(version))
+            if replaced && versions.join(version.dir_name()).exists() =>
+        {
+            Ok(activate_version(versions, version, target)?)
+        }
+        _ => remove_file(target)
+            .await
+            .map_err(|source| InstallerError::Remove {
+                path: target.to_owned(),
+                source,
+            }),
+    }
+}
+
+fn rollback_status(rollback: &Option<Box<InstallerError>>) -> String {
+    match rollback {
+        Some(e) => format!("failed to roll back the update: {e}"),
+        None => "rolled back the update".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use googletest::prelude::*;
+    use std::str::FromStr;
+
+    #[gtest]
+    fn plan_is_up_to_date_works() {
+        let version = |s: &str| ToolVersion::from_str(s).unwrap();
+        let plan = |current: Option<&str>, pinned| Plan {
+            current_version: current.map(version),
+            release: Release {
+                tag_name: "v1.2.0".into(),
+                body: String::new(),
+                created_at: Timestamp::UNIX_EPOCH,
+                prerelease: false,
+                assets: Vec::new(),
+            },
+            release_version: version("1.2.0"),
+            pinned,
+        };
+        expect_true!(plan(Some("1.2.0"), false).is_up_to_date());
+        expect_true!(plan(Some("1.3.0"), false).is_up_to_date());
+        expect_false!(plan(Some("1.1.0"), false).is_up_to_date());
+        expect_false!(plan(None, false).is_up_to_date());
+        // Pinned repos are downgraded to the pinned release
+        expect_false!(plan(Some("1.3.0"), true).is_up_to_date());
+        expect_true!(plan(Some("1.2.0"), true).is_up_to_date());
+    }
+}
@@ -1,4 +1,12 @@
+pub mod cache;
 pub mod config;
 pub mod domain;
 pub mod github_client;
+pub mod hooks;
+pub mod install;
+pub mod installer;
+pub mod lockfile;
+pub mod manifest;
+pub mod reporter;
+pub mod toolset;
 pub mod utils;
@@ -0,0 +1,162 @@
+use crate::config::RepoConfig;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+use tokio::fs::{read_to_string, write};
+
+/// Lockfile name, kept next to the config file so it can be shared with it
+pub const LOCKFILE: &str = "dl-releases.lock";
+
+const HEADER: &str = "# Generated by `dl-releases lock`, it shouldn't be edited by hand.\n\n";
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum LockfileError {
+    #[error("Failed to read {path:?}.")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to write to {path:?}.")]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to deserialize {path:?}.")]
+    Deserialize {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("Failed to serialize lockfile.")]
+    Serialize(#[from] toml::ser::Error),
+}
+
+/// Exact releases to install for the repositories of a config
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Lockfile {
+    /// Locked releases by repository name (user/repo_name)
+    #[serde(default)]
+    pub repos: BTreeMap<String, LockedRelease>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedRelease {
+    pub tag: String,
+    /// Name of the asset to download
+    pub asset: String,
+    pub url: String,
+    /// Sha256 digest of the asset, if the release provides one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+impl LockedRelease {
+    /// Whether this release still follows `repo_config`, its pin and pattern
+    pub fn matches(&self, repo_config: &RepoConfig) -> bool {
+        repo_config.pin.as_ref().is_none_or(|o| o == &self.tag)
+            && self.asset.to_lowercase().contains(&repo_config.pat)
+    }
+}
+
+impl Lockfile {
+    /// Path of the lockfile of the config at `config_path`
+    pub fn path(config_path: &Path) -> PathBuf {
+        config_path.with_file_name(LOCKFILE)
+    }
+
+    /// Reads the lockfile at `path`, an empty one is returned if it doesn't exist
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self, LockfileError> {
+        let path = path.as_ref().to_owned();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let s = match read_to_string(&path).await {
+            Ok(s) => s,
+            Err(source) => return Err(LockfileError::Read { path, source }),
+        };
+        toml::from_str(&s).map_err(|source| LockfileError::Deserialize { path, source })
+    }
+
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<(), LockfileError> {
+        let path = path.as_ref().to_owned();
+        let s = format!("{HEADER}{}", toml::to_string_pretty(self)?);
+        write(&path, s)
+            .await
+            .map_err(|source| LockfileError::Write { path, source })
+    }
+
+    pub fn get(&self, repo: &str) -> Option<&LockedRelease> {
+        self.repos.get(repo)
+    }
+
+    pub fn insert(&mut self, repo: String, release: LockedRelease) {
+        self.repos.insert(repo, release);
+    }
+
+    /// Removes the repositories for which `f` returns false, returns their names
+    pub fn retain(&mut self, mut f: impl FnMut(&str) -> bool) -> Vec<String> {
+        let removed = self
+            .repos
+            .keys()
+            .filter(|o| !f(o))
+            .cloned()
+            .collect::<Vec<_>>();
+        for repo in &removed {
+            self.repos.remove(repo);
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use googletest::prelude::*;
+    use tempfile::tempdir;
+
+    #[gtest]
+    #[tokio::test]
+    async fn lockfile_roundtrip_works() {
+        let dir = tempdir().unwrap();
+        let path = Lockfile::path(&dir.path().join("config.toml"));
+        let mut lockfile = Lockfile::load(&path).await.unwrap();
+        expect_that!(lockfile.repos, is_empty());
+        let release = LockedRelease {
+            tag: "v0.54.1".to_string(),
+            asset: "lazygit_0.54.1_linux_x86_64.tar.gz".to_string(),
+            url: "https://github.com/jesseduffield/lazygit/releases/download/v0.54.1/lazygit_0.54.1_linux_x86_64.tar.gz".to_string(),
+            sha256: Some("0".repeat(64)),
+        };
+        lockfile.insert("jesseduffield/lazygit".to_string(), release.clone());
+        lockfile.insert("sharkdp/fd".to_string(), release.clone());
+        lockfile.save(&path).await.unwrap();
+        let mut lockfile = Lockfile::load(&path).await.unwrap();
+        expect_that!(lockfile.get("jesseduffield/lazygit"), some(eq(&release)));
+
+        let removed = lockfile.retain(|o| o != "sharkdp/fd");
+        expect_that!(removed, elements_are![eq("sharkdp/fd")]);
+        expect_that!(lockfile.get("sharkdp/fd"), none());
+    }
+
+    #[gtest]
+    fn locked_release_matches_works() {
+        let release = LockedRelease {
+            tag: "v0.54.1".to_string(),
+            asset: "lazygit_0.54.1_Linux_x86_64.tar.gz".to_string(),
+            url: String::new(),
+            sha256: None,
+        };
+        let mut config = RepoConfig::new(
+            "jesseduffield/lazygit".to_string(),
+            "linux_x86_64".to_string(),
+        );
+        expect_true!(release.matches(&config));
+        config.pin = Some("v0.50.0".to_string());
+        expect_false!(release.matches(&config));
+        config.pin = Some("v0.54.1".to_string());
+        config.pat = "darwin".to_string();
+        expect_false!(release.matches(&config));
+    }
+}
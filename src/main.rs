@@ -1,61 +1,501 @@
 use anyhow::Context;
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+use console::style;
 use dialoguer::Confirm;
 use dl_releases::{
-    config::{RepoConfig, get_binaries_path, get_config_path, get_configuration, get_data_path},
-    domain::Repository,
-    github_client::GithubClient,
-    utils::{extract_file_async, get_version},
+    cache::Cache,
+    config::{
+        ConfigEditor, ConfigError, RepoConfig, Settings, SettingsOverrides, check_config,
+        get_binaries_path, get_config_path, get_configuration, get_data_path, get_settings,
+    },
+    domain::{Asset, Repository, ToolVersion},
+    github_client::{Capture, GithubClient},
+    install::{
+        InstallLayout, activate_version, backups_dir, list_versions, prune_versions,
+        rollback_binary, versions_dir,
+    },
+    installer::{Installer, InstallerBuilder, Outcome, RepoOutcome, Update},
+    lockfile::Lockfile,
+    manifest::{InstalledTool, Manifest},
+    reporter::Reporter,
+    toolset::ToolSet,
+    utils::get_version_with,
 };
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
 use itertools::Itertools;
+use jiff::{Span, Timestamp, Zoned};
 use std::{
     path::{Path, PathBuf},
     time::Duration,
 };
-use tokio::fs::write;
-
-// TODO: add option to show release changelog
+use termimad::MadSkin;
 
 /// Personal utility to download and install binaries from git releases
 #[derive(Debug, Parser)]
 #[command(version)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<ArgsCommand>,
     /// Repository name in format user/repo_name
     #[arg(short, long)]
     repo: Option<Repository>,
     /// Pattern to look in into assets to pick the one to download
     #[arg(short, long)]
     pat: Option<String>,
-    /// Output path to extract binaries
-    #[arg(short, long)]
+    /// Config file to use instead of ~/.config/dl-releases/config.toml
+    #[arg(short, long, global = true, env = "DL_RELEASES_CONFIG")]
+    config: Option<PathBuf>,
+    /// Output path to extract binaries [default: settings.download_dir]
+    #[arg(short, long, global = true)]
     outpath: Option<PathBuf>,
-    /// Final binaries location (eg: ~/.local/bin/)
-    #[arg(short, long)]
+    /// Final binaries location (eg: ~/.local/bin/) [default: settings.binaries_dir]
+    #[arg(short, long, global = true)]
     binaries_location: Option<PathBuf>,
+    /// Maximum number of repositories to update concurrently [default: settings.jobs]
+    #[arg(short, long, value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: Option<u16>,
+    /// Show the release notes of updated repositories
+    #[arg(long)]
+    show_changelog: bool,
+    /// Save the API responses and downloaded assets into this directory
+    #[arg(long, global = true, conflicts_with = "replay")]
+    record: Option<PathBuf>,
+    /// Serve the API responses and assets from a directory made with --record instead
+    /// of doing requests
+    #[arg(long, global = true)]
+    replay: Option<PathBuf>,
+    /// Install from the cached releases and downloads only, without doing requests
+    #[arg(long, global = true, env = "DL_RELEASES_OFFLINE")]
+    offline: bool,
+}
+
+/// Subcommands, with the ones running without the settings apart
+#[derive(Debug, Subcommand)]
+enum ArgsCommand {
+    #[command(flatten)]
+    Command(Command),
+    /// Inspect the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Restore the binary installed before the last update of a repository
+    Rollback {
+        /// Repository name in format user/repo_name
+        repo: Repository,
+    },
+    /// Show the release notes of a repository, from the installed version to the latest
+    Changelog {
+        /// Repository name in format user/repo_name
+        repo: Repository,
+        /// Show notes for releases newer than this version instead of the installed one
+        #[arg(long)]
+        from: Option<ToolVersion>,
+    },
+    /// Remove a repository from the config file
+    Remove {
+        /// Repository name in format user/repo_name
+        repo: Repository,
+    },
+    /// Change the pattern used to pick the asset of a repository
+    SetPattern {
+        /// Repository name in format user/repo_name
+        repo: Repository,
+        /// Pattern to look in into assets to pick the one to download
+        pat: String,
+    },
+    /// Pin a repository to a release tag instead of following the latest release
+    Pin {
+        /// Repository name in format user/repo_name
+        repo: Repository,
+        /// Release tag (eg: v1.2.3)
+        tag: String,
+    },
+    /// Follow the latest release of a pinned repository again
+    Unpin {
+        /// Repository name in format user/repo_name
+        repo: Repository,
+    },
+    /// Switch to an installed version of a repository (versioned layout)
+    Use {
+        /// Repository name in format user/repo_name
+        repo: Repository,
+        /// Version to activate, parsed with the version scheme of the repository
+        version: String,
+    },
+    /// Install a release asset copied manually, without doing requests
+    Install {
+        /// Archive to install, checked against the cached release metadata if any
+        #[arg(long)]
+        from_file: PathBuf,
+        /// Repository name in format user/repo_name
+        #[arg(short, long)]
+        repo: Repository,
+    },
+    /// Install the releases recorded in the lockfile, locking the repositories of the
+    /// config missing from it first
+    Sync {
+        /// Fail if the lockfile is missing repositories of the config or out of date
+        /// instead of updating it
+        #[arg(long)]
+        locked: bool,
+    },
+    /// Record the release of every repository of the config in the lockfile
+    Lock {
+        /// Resolve the locked releases again, of every repository or only this one
+        #[arg(long, value_name = "REPO", num_args = 0..=1)]
+        update: Option<Option<Repository>>,
+    },
+    /// Print the installed tools with their config and exact versions
+    Export {
+        #[arg(long, value_enum, default_value_t)]
+        format: ExportFormat,
+        /// Write to this file instead of the standard output
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Add the tools of a file made with `export` to the config and install them
+    Import {
+        /// Tool set to import, read as JSON if it ends in `.json` and TOML otherwise
+        file: PathBuf,
+    },
+    /// Delete old versions of repositories using the versioned layout
+    Prune {
+        /// Repository name in format user/repo_name, all versioned repositories in the
+        /// config are pruned if missing
+        repo: Option<Repository>,
+        /// Number of versions to keep, the active version is always kept
+        #[arg(short, long, default_value_t = 3)]
+        keep: usize,
+    },
+    /// Inspect and clean the cache of downloaded assets
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+enum ExportFormat {
+    #[default]
+    Toml,
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommand {
+    /// Validate every entry of the config file and its includes
+    Check,
+}
+
+#[derive(Debug, Subcommand)]
+enum CacheCommand {
+    /// List the cached assets with their size
+    Ls,
+    /// Remove cached assets and incomplete downloads
+    Clean {
+        /// Only remove assets downloaded longer ago than this (eg: "30d", "2w")
+        #[arg(long)]
+        older_than: Option<Span>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let Args {
+        command,
         repo,
         pat,
+        config,
         outpath,
         binaries_location,
+        jobs,
+        show_changelog,
+        record,
+        replay,
+        offline,
     } = Args::parse();
-    let config_path = get_config_path().await?;
-    let outpath = match outpath {
+    let capture = match (record, replay) {
+        (Some(dir), _) => Some(Capture::Record(dir)),
+        (_, Some(dir)) => Some(Capture::Replay(dir)),
+        (None, None) => None,
+    };
+    let config_path = get_config_path(config).await?;
+    let command = match command {
+        // Runs before reading the settings so it can report problems on them
+        Some(ArgsCommand::Config {
+            command: ConfigCommand::Check,
+        }) => return check_config_file(&config_path),
+        Some(ArgsCommand::Command(o)) => Some(o),
+        None => None,
+    };
+    let overrides = SettingsOverrides {
+        binaries_dir: binaries_location,
+        download_dir: outpath,
+        jobs,
+    };
+    let settings = get_settings(&config_path, &overrides)?;
+    let outpath = match settings.download_dir.clone() {
         Some(x) => x,
         None => get_data_path().await?,
     };
-    let binaries_location = match binaries_location {
+    let binaries_location = match settings.binaries_dir.clone() {
         Some(x) => x,
         None => get_binaries_path()?,
     };
+    if let Some(command) = command {
+        match command {
+            Command::Rollback { repo } => {
+                let repo_config = find_repo_config(&config_path, &repo)?;
+                let mut manifest = Manifest::load(&outpath).await?;
+                let name = repo.to_string();
+                if repo_config.trust_release_version && !manifest.backups.contains_key(&name) {
+                    anyhow::bail!("No previous version of {repo} in the manifest.");
+                }
+                let target = binaries_location.join(&repo.repository);
+                rollback_binary(&target, backups_dir(&outpath))?;
+                manifest.rollback(&name);
+                let version = if repo_config.trust_release_version {
+                    manifest
+                        .get(&name)
+                        .map(InstalledTool::version)
+                        .transpose()?
+                } else {
+                    None
+                };
+                let version = match version {
+                    Some(version) => version,
+                    None => get_version_with(&target, &repo_config.version_probe()?).await?,
+                };
+                if let Some(tool) = manifest.tools.get_mut(&name) {
+                    tool.version = version.to_string();
+                }
+                manifest.save(&outpath).await?;
+                println!("✓ [{}] Rolled back to version {version}.", repo.repository);
+            }
+            Command::Changelog { repo, from } => {
+                let repo_config = find_repo_config(&config_path, &repo)?;
+                let from = match from {
+                    Some(from) => Some(from),
+                    None => Manifest::load(outpath)
+                        .await?
+                        .get(&repo.to_string())
+                        .map(InstalledTool::version)
+                        .transpose()?,
+                };
+                let client = new_client(&settings, capture.as_ref(), offline).await?;
+                let release = client
+                    .get_latest_release(&repo)
+                    .await
+                    .context("Failed to get latest release.")?;
+                let to = release.version(repo_config.version_scheme)?;
+                print_changelog(&client, &repo, &repo_config, from.as_ref(), &to).await?;
+            }
+            Command::Remove { repo } => {
+                let path =
+                    edit_repo_config(&config_path, &repo, |o, repo| o.remove_repo(repo)).await?;
+                println!("Removed {repo} from {path:?}");
+            }
+            Command::SetPattern { repo, pat } => {
+                edit_repo_config(&config_path, &repo, |o, repo| o.set_pattern(repo, &pat)).await?;
+                println!("Updated pattern of {repo} to {pat:?}");
+            }
+            Command::Pin { repo, tag } => {
+                edit_repo_config(&config_path, &repo, |o, repo| o.pin(repo, Some(&tag))).await?;
+                println!("Pinned {repo} to {tag:?}");
+            }
+            Command::Unpin { repo } => {
+                edit_repo_config(&config_path, &repo, |o, repo| o.pin(repo, None)).await?;
+                println!("Unpinned {repo}");
+            }
+            Command::Use { repo, version } => {
+                let repo_config = find_repo_config(&config_path, &repo)?;
+                let versions = versions_dir(&outpath, &repo);
+                let installed = list_versions(&versions)?;
+                let found = repo_config
+                    .version_scheme
+                    .extract(&version)
+                    .ok()
+                    .and_then(|o| installed.iter().find(|installed| *installed == &o));
+                let Some(version) = found.cloned() else {
+                    anyhow::bail!(
+                        "Version {version:?} of {repo} is not installed, available versions: {}.",
+                        installed.iter().join(", ")
+                    );
+                };
+                let target = binaries_location.join(&repo.repository);
+                activate_version(&versions, &version, &target)?;
+                set_manifest_version(&outpath, &repo, &version).await?;
+                println!("✓ [{}] Using version {version}.", repo.repository);
+            }
+            Command::Install { from_file, repo } => {
+                let repo_config = find_repo_config(&config_path, &repo)?;
+                let m = MultiProgress::new();
+                let client = new_client(&settings, capture.as_ref(), true).await?;
+                let installer =
+                    installer_builder(client, &settings, outpath, binaries_location, true, &m)
+                        .build();
+                installer
+                    .install_file(&repo, &repo_config, &from_file)
+                    .await
+                    .with_context(|| format!("Failed to install {from_file:?}"))?;
+            }
+            Command::Sync { locked } => {
+                let m = MultiProgress::new();
+                let client = new_client(&settings, capture.as_ref(), offline).await?;
+                let resolver = installer_builder(
+                    client.clone(),
+                    &settings,
+                    outpath.clone(),
+                    binaries_location.clone(),
+                    offline,
+                    &m,
+                )
+                .build();
+                let lockfile = update_lockfile(&config_path, &resolver, |_| false, locked).await?;
+                let installer =
+                    installer_builder(client, &settings, outpath, binaries_location, offline, &m)
+                        .lockfile(lockfile)
+                        .build();
+                // Release notes can't be fetched offline
+                let show_changelog = show_changelog && !offline;
+                execute_from_config(config_path, &installer, &m, show_changelog).await?;
+            }
+            Command::Lock { update } => {
+                if let Some(Some(repo)) = &update
+                    && !get_configuration(&config_path)?
+                        .read_repositories()?
+                        .iter()
+                        .any(|(o, _)| o == repo)
+                {
+                    anyhow::bail!("{repo} is not in {config_path:?}.");
+                }
+                let m = MultiProgress::new();
+                let client = new_client(&settings, capture.as_ref(), offline).await?;
+                let installer =
+                    installer_builder(client, &settings, outpath, binaries_location, offline, &m)
+                        .build();
+                let is_updated = |repo: &Repository| match &update {
+                    Some(Some(o)) => o == repo,
+                    Some(None) => true,
+                    None => false,
+                };
+                update_lockfile(&config_path, &installer, is_updated, false).await?;
+            }
+            Command::Export { format, output } => {
+                let manifest = Manifest::load(&outpath).await?;
+                let repos = get_configuration(&config_path)?.read_repositories()?;
+                let toolset = ToolSet::from_installed(&manifest, &repos);
+                let s = match format {
+                    ExportFormat::Toml => toolset.to_toml()?,
+                    ExportFormat::Json => toolset.to_json()?,
+                };
+                match output {
+                    Some(path) => {
+                        tokio::fs::write(&path, s)
+                            .await
+                            .with_context(|| format!("Failed to write to {path:?}"))?;
+                        println!("Exported {} tools to {path:?}", toolset.tools.len());
+                    }
+                    None => print!("{s}"),
+                }
+            }
+            Command::Import { file } => {
+                let toolset = ToolSet::load(&file).await?;
+                let repos = get_configuration(&config_path)?.read_repositories()?;
+                let merge = toolset.merge(&repos)?;
+                for conflict in &merge.conflicts {
+                    println!("{} {conflict}", style("conflict:").bold().yellow());
+                }
+                add_to_config(&config_path, &merge.added).await?;
+                println!(
+                    "Imported {} tools: {} added, {} already in the config, {} conflicts.",
+                    toolset.tools.len(),
+                    merge.added.len(),
+                    merge.unchanged.len(),
+                    merge.conflicts.len()
+                );
+                if merge.added.iter().any(|o| o.pin.is_some()) {
+                    println!(
+                        "Added repos are pinned to the exported releases, `unpin` them to follow the latest ones."
+                    );
+                }
+                let m = MultiProgress::new();
+                let client = new_client(&settings, capture.as_ref(), offline).await?;
+                let installer =
+                    installer_builder(client, &settings, outpath, binaries_location, offline, &m)
+                        .build();
+                execute_from_config(config_path, &installer, &m, false).await?;
+            }
+            Command::Cache { command } => {
+                let cache = Cache::new(&outpath);
+                match command {
+                    CacheCommand::Ls => {
+                        let entries = cache.list()?;
+                        for entry in &entries {
+                            println!(
+                                "{:>10}  {}  {}",
+                                HumanBytes(entry.size).to_string(),
+                                entry.downloaded_at.strftime("%Y-%m-%d"),
+                                entry.name
+                            );
+                        }
+                        let total = entries.iter().map(|o| o.size).sum();
+                        println!("{} assets, {} in total.", entries.len(), HumanBytes(total));
+                    }
+                    CacheCommand::Clean { older_than } => {
+                        let before = match older_than {
+                            Some(span) => Zoned::now().checked_sub(span)?.timestamp(),
+                            None => Timestamp::MAX,
+                        };
+                        let (removed, freed) = cache.clean(before)?;
+                        println!(
+                            "✓ Removed {removed} cache entries, freed {}.",
+                            HumanBytes(freed)
+                        );
+                    }
+                }
+            }
+            Command::Prune { repo, keep } => {
+                let repos = match repo {
+                    Some(repo) => vec![repo],
+                    None => get_configuration(&config_path)?
+                        .read_repositories()?
+                        .into_iter()
+                        .filter(|(_, o)| o.layout == InstallLayout::Versioned)
+                        .map(|(repo, _)| repo)
+                        .collect(),
+                };
+                for repo in repos {
+                    let target = binaries_location.join(&repo.repository);
+                    let removed = prune_versions(versions_dir(&outpath, &repo), &target, keep)?;
+                    let s = removed.iter().join(", ");
+                    println!(
+                        "✓ [{}] Removed {} versions: {s}",
+                        repo.repository,
+                        removed.len()
+                    );
+                }
+            }
+        }
+        return Ok(());
+    }
+    let m = MultiProgress::new();
+    let client = new_client(&settings, capture.as_ref(), offline).await?;
+    let installer =
+        installer_builder(client, &settings, outpath, binaries_location, offline, &m).build();
+    // Release notes can't be fetched offline
+    let show_changelog = show_changelog && !offline;
     match (repo, pat) {
-        (None, None) => execute_from_config(config_path, outpath, binaries_location).await?,
+        (None, None) => {
+            execute_from_config(config_path, &installer, &m, show_changelog).await?;
+        }
         (Some(repo), Some(pat)) => {
-            execute_from_args(config_path, outpath, binaries_location, repo, pat).await?;
+            execute_from_args(config_path, &installer, repo, pat, show_changelog).await?;
         }
         _ => {
             anyhow::bail!("`repo` and `pat` should be defined together.");
@@ -64,132 +504,419 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn execute_from_config(
-    config_path: PathBuf,
+async fn new_client(
+    settings: &Settings,
+    capture: Option<&Capture>,
+    offline: bool,
+) -> anyhow::Result<GithubClient> {
+    // Replays and offline installs don't do requests, so there's no need to run the
+    // token command
+    let token = match capture {
+        _ if offline => None,
+        Some(Capture::Replay(_)) => None,
+        _ => settings.token().await?,
+    };
+    let options = settings.client_options()?;
+    let client = GithubClient::new(&settings.api_url, token.as_deref(), &options)?;
+    Ok(match capture {
+        Some(capture) => client.with_capture(capture.clone()),
+        None => client,
+    })
+}
+
+/// Installer showing its progress in `m`
+fn installer_builder(
+    client: GithubClient,
+    settings: &Settings,
     outpath: PathBuf,
     binaries_location: PathBuf,
+    offline: bool,
+    m: &MultiProgress,
+) -> InstallerBuilder {
+    let m = m.clone();
+    Installer::builder(client, outpath, binaries_location)
+        .reporter(move |repo| Box::new(BarsReporter::new(&m, &repo.repository)))
+        .keep_downloads(settings.keep_downloads)
+        .checksum(settings.checksum)
+        .jobs(settings.jobs)
+        .offline(offline)
+}
+
+/// Config of `repo` in the config file, or a default one if it's not there
+fn find_repo_config(config_path: &Path, repo: &Repository) -> anyhow::Result<RepoConfig> {
+    Ok(get_configuration(config_path)?
+        .read_repositories()?
+        .into_iter()
+        .find(|(o, _)| o == repo)
+        .map(|(_, o)| o)
+        .unwrap_or_else(|| RepoConfig::new(repo.to_string(), String::new())))
+}
+
+fn check_config_file(config_path: &Path) -> anyhow::Result<()> {
+    let diagnostics = check_config(config_path);
+    if diagnostics.is_empty() {
+        println!("✓ No problems found in {config_path:?}.");
+        return Ok(());
+    }
+    for diagnostic in &diagnostics {
+        println!("{} {diagnostic}", style("error:").bold().red());
+    }
+    anyhow::bail!("Found {} problems in the config.", diagnostics.len());
+}
+
+/// Applies `f` to the config file, preserving its comments and formatting, the file
+/// isn't changed if the edited config is not valid
+async fn edit_config(
+    config_path: &Path,
+    f: impl FnOnce(&mut ConfigEditor) -> Result<(), ConfigError>,
 ) -> anyhow::Result<()> {
-    let config = get_configuration(&config_path)?.read_repositories()?;
-    let client = GithubClient::new()?;
-    let m = MultiProgress::new();
-    for (repo, pat) in config {
-        if let Err(e) = handle_repo(&m, &client, &repo, &pat, &outpath, &binaries_location).await {
-            println!(
-                "Failed to handle repo \"{repo}\" with pat=\"{pat}\": {e}\nError details: {e:?}"
-            );
+    let mut editor = ConfigEditor::open(config_path).await?;
+    f(&mut editor)?;
+    editor.save().await?;
+    Ok(())
+}
+
+/// Edits the config file defining `repo`, which may be included by the one at
+/// `config_path`, and returns its path
+async fn edit_repo_config(
+    config_path: &Path,
+    repo: &Repository,
+    f: impl FnOnce(&mut ConfigEditor, &str) -> Result<(), ConfigError>,
+) -> anyhow::Result<PathBuf> {
+    let repo = repo.to_string();
+    let mut editor = ConfigEditor::open_for_repo(config_path, &repo).await?;
+    f(&mut editor, &repo)?;
+    editor.save().await?;
+    Ok(editor.path().to_owned())
+}
+
+/// Locks the repositories of the config missing from the lockfile, not matching their
+/// config anymore or for which `is_updated` returns true, and unlocks the ones removed
+/// from the config. With `locked`, fails if the lockfile should change instead.
+async fn update_lockfile(
+    config_path: &Path,
+    installer: &Installer,
+    is_updated: impl Fn(&Repository) -> bool,
+    locked: bool,
+) -> anyhow::Result<Lockfile> {
+    let path = Lockfile::path(config_path);
+    let mut lockfile = Lockfile::load(&path).await?;
+    let repos = get_configuration(config_path)?.read_repositories()?;
+    let removed = lockfile.retain(|o| repos.iter().any(|(repo, _)| repo.to_string() == o));
+    let outdated = repos
+        .iter()
+        .filter(|(repo, config)| {
+            is_updated(repo)
+                || lockfile
+                    .get(&repo.to_string())
+                    .is_none_or(|o| !o.matches(config))
+        })
+        .collect::<Vec<_>>();
+    if removed.is_empty() && outdated.is_empty() {
+        return Ok(lockfile);
+    }
+    if locked {
+        let s = outdated
+            .iter()
+            .map(|(o, _)| o.to_string())
+            .chain(removed)
+            .join(", ");
+        anyhow::bail!("The lockfile {path:?} is out of date ({s}) and --locked was passed.");
+    }
+    for repo in &removed {
+        println!("Unlocked {repo}");
+    }
+    for (repo, config) in outdated {
+        let release = installer
+            .lock(repo, config)
+            .await
+            .with_context(|| format!("Failed to lock {repo}"))?;
+        println!("Locked {repo} to {} ({})", release.tag, release.asset);
+        lockfile.insert(repo.to_string(), release);
+    }
+    lockfile.save(&path).await?;
+    Ok(lockfile)
+}
+
+/// Updates the manifest after switching binaries without installing a release
+async fn set_manifest_version(
+    outpath: &Path,
+    repo: &Repository,
+    version: &ToolVersion,
+) -> anyhow::Result<()> {
+    let mut manifest = Manifest::load(outpath).await?;
+    if let Some(tool) = manifest.tools.get_mut(&repo.to_string()) {
+        tool.version = version.to_string();
+        manifest.save(outpath).await?;
+    }
+    Ok(())
+}
+
+async fn execute_from_config(
+    config_path: PathBuf,
+    installer: &Installer,
+    m: &MultiProgress,
+    show_changelog: bool,
+) -> anyhow::Result<()> {
+    let config = get_configuration(&config_path)?;
+    let outcomes = installer.update_all(config).await?;
+    let mut succeeded = 0;
+    let mut failed = Vec::new();
+    let mut updates = Vec::new();
+    for RepoOutcome {
+        repo,
+        config,
+        result,
+    } in outcomes
+    {
+        match result {
+            Ok(outcome) => {
+                succeeded += 1;
+                if let Outcome::Installed(update) = outcome {
+                    updates.push((repo, config, update));
+                }
+            }
+            Err(e) => {
+                let e = anyhow::Error::from(e);
+                m.println(format!(
+                    "Failed to handle repo \"{repo}\" with pat=\"{}\": {e}\nError details: {e:?}",
+                    config.pat
+                ))
+                .ok();
+                failed.push(repo);
+            }
+        }
+    }
+    if show_changelog {
+        for (repo, config, update) in &updates {
+            // Release notes are best-effort, the updates already happened
+            if let Err(e) = print_update_changelog(installer.client(), repo, config, update).await {
+                m.println(format!("Failed to show the release notes of {repo}: {e:#}"))
+                    .ok();
+            }
         }
     }
+    println!(
+        "Finished: {} succeeded, {} failed.",
+        succeeded,
+        failed.len()
+    );
+    if !failed.is_empty() {
+        let s = failed.iter().join(", ");
+        println!("Failed repos: {s}.");
+    }
     Ok(())
 }
 
 async fn execute_from_args(
     config_path: PathBuf,
-    outpath: PathBuf,
-    binaries_location: PathBuf,
+    installer: &Installer,
     repo: Repository,
     pat: String,
+    show_changelog: bool,
 ) -> anyhow::Result<()> {
-    let client = GithubClient::new()?;
-    let m = MultiProgress::new();
-    handle_repo(&m, &client, &repo, &pat, &outpath, &binaries_location)
+    let repo_config = RepoConfig::new(repo.to_string(), pat);
+    let outcome = installer
+        .install(&repo, &repo_config)
         .await
         .context("Failed to handle repo")?;
-    let mut config = get_configuration(&config_path)?;
-    let repo = repo.to_string();
-    if config.repos.iter().map(|o| &o.repo).contains(&repo) {
+    if let Some(update) = outcome.update()
+        && show_changelog
+        && let Err(e) =
+            print_update_changelog(installer.client(), &repo, &repo_config, update).await
+    {
+        println!("Failed to show the release notes of {repo}: {e:#}");
+    }
+    let config = get_configuration(&config_path)?;
+    if config.repos.iter().any(|o| o.repo == repo_config.repo) {
         return Ok(());
     }
-    let add_to_config = Confirm::new()
+    let confirmed = Confirm::new()
         .with_prompt(format!(
             "Do you want to add this repository/pattern to your config file ({config_path:?})?"
         ))
         .interact()
         .unwrap();
-    if add_to_config {
-        config.repos.push(RepoConfig {
-            repo: repo.clone(),
-            pat,
-        });
-        let s = toml::to_string_pretty(&config).context("Failed to serialize config.")?;
-        write(&config_path, s)
-            .await
-            .context("Failed to write to file.")?;
-        println!("Added {repo} to {config_path:?}");
+    if confirmed {
+        add_to_config(&config_path, &[repo_config]).await?;
     }
     Ok(())
 }
 
-/// Downloads the last release and installs it if required
-async fn handle_repo(
-    m: &MultiProgress,
+/// Appends `repo_configs` to the config file
+async fn add_to_config(config_path: &Path, repo_configs: &[RepoConfig]) -> anyhow::Result<()> {
+    if repo_configs.is_empty() {
+        return Ok(());
+    }
+    edit_config(config_path, |o| {
+        repo_configs
+            .iter()
+            .try_for_each(|repo_config| o.add_repo(repo_config))
+    })
+    .await?;
+    for repo_config in repo_configs {
+        println!("Added {} to {config_path:?}", repo_config.repo);
+    }
+    Ok(())
+}
+
+/// Prints the release notes of the releases installed by `update`
+async fn print_update_changelog(
     client: &GithubClient,
     repo: &Repository,
-    pat: &str,
-    outpath: &Path,
-    binaries_location: &Path,
+    repo_config: &RepoConfig,
+    update: &Update,
 ) -> anyhow::Result<()> {
-    let pb1 = m.add(
-        ProgressBar::no_length()
-            .with_style(
-                ProgressStyle::with_template("{spinner} {prefix} {msg} [{elapsed_precise}] [{wide_bar}] {bytes}/{total_bytes} ({eta})")
-                    .unwrap()
-                    .progress_chars("#>-"),
-            )
-            .with_prefix("[1/3]"),
-    );
-    let pb2 = m
-        .add(
-            ProgressBar::new_spinner()
-                .with_style(ProgressStyle::with_template("{spinner} {prefix} {wide_msg}").unwrap())
-                .with_prefix("[2/3]"),
-        )
-        .with_message("Waiting to extract file...");
-    let pb3 = m
-        .add(
-            ProgressBar::new_spinner()
-                .with_style(ProgressStyle::with_template("{spinner} {prefix} {wide_msg}").unwrap())
-                .with_prefix("[3/3]"),
-        )
-        .with_message("Waiting to check new version...");
-    pb2.enable_steady_tick(Duration::from_millis(100));
-    pb3.enable_steady_tick(Duration::from_millis(100));
-    let current_version = get_version(&repo.repository).await?;
-    let release = client
-        .get_latest_release(repo)
+    let version = update.tool.version()?;
+    let previous_version = update.previous_version.as_ref();
+    print_changelog(client, repo, repo_config, previous_version, &version).await
+}
+
+/// Prints the release notes between `from` and `to` rendered as markdown
+async fn print_changelog(
+    client: &GithubClient,
+    repo: &Repository,
+    repo_config: &RepoConfig,
+    from: Option<&ToolVersion>,
+    to: &ToolVersion,
+) -> anyhow::Result<()> {
+    let notes = client
+        .get_changelog(repo, repo_config.version_scheme, from, to)
         .await
-        .context("Failed to get latest release.")?;
-    let release_version = release.version()?;
-    if release_version > current_version {
-        let asset = release.find_asset(pat)?;
-        pb1.set_length(asset.size);
-        let path = client.download_asset(repo, asset, outpath, &pb1).await?;
-        let extracted_path =
-            extract_file_async(path, &repo.repository, binaries_location, &pb2).await?;
-        pb2.with_style(ProgressStyle::with_template("{msg:.green}").unwrap())
-            .finish_with_message(format!(
-                "✓ [{}] Extracted to {extracted_path:?}.",
-                repo.repository
-            ));
-        let extracted_version = get_version(extracted_path).await?;
-        if extracted_version != release_version {
-            anyhow::bail!(
-                "extracted_version ({release_version}) doesn't match the downloaded one ({extracted_version})."
+        .context("Failed to get changelog.")?;
+    let skin = MadSkin::default();
+    println!("{}", style(format!("Changelog for {repo}")).bold().green());
+    skin.print_text(&notes);
+    Ok(())
+}
+
+/// Shows the progress of a repository update with a [`MultiProgress`] line per step
+struct BarsReporter {
+    m: MultiProgress,
+    name: String,
+    download: ProgressBar,
+    extract: ProgressBar,
+    install: ProgressBar,
+}
+
+impl BarsReporter {
+    fn new(m: &MultiProgress, name: &str) -> Self {
+        let download = m.add(
+            ProgressBar::no_length()
+                .with_style(
+                    ProgressStyle::with_template("{spinner} {prefix} {msg} [{elapsed_precise}] [{wide_bar}] {bytes}/{total_bytes} ({eta})")
+                        .unwrap()
+                        .progress_chars("#>-"),
+                )
+                .with_prefix("[1/3]"),
+        );
+        let extract = m
+            .add(
+                ProgressBar::new_spinner()
+                    .with_style(
+                        ProgressStyle::with_template("{spinner} {prefix} {wide_msg}").unwrap(),
+                    )
+                    .with_prefix("[2/3]"),
+            )
+            .with_message("Waiting to extract file...");
+        let install = m
+            .add(
+                ProgressBar::new_spinner()
+                    .with_style(
+                        ProgressStyle::with_template("{spinner} {prefix} {wide_msg}").unwrap(),
+                    )
+                    .with_prefix("[3/3]"),
             )
+            .with_message("Waiting to check new version...");
+        extract.enable_steady_tick(Duration::from_millis(100));
+        install.enable_steady_tick(Duration::from_millis(100));
+        Self {
+            m: m.clone(),
+            name: name.to_owned(),
+            download,
+            extract,
+            install,
         }
-        pb3.with_style(ProgressStyle::with_template("{msg:.green}").unwrap())
-            .finish_with_message(format!(
-                "✓ [{}] Updated to version {extracted_version}.",
-                repo.repository
-            ));
-        Ok(())
-    } else {
-        m.remove(&pb2);
-        m.remove(&pb3);
-        pb1.with_style(ProgressStyle::with_template("{msg:.green}").unwrap())
-            .finish_with_message(format!(
-                "✓ [{}] is up to date: {current_version}",
-                repo.repository
-            ));
-        Ok(())
+    }
+}
+
+impl Reporter for BarsReporter {
+    fn download_started(&self, asset: &Asset) {
+        self.download.set_length(asset.size);
+        self.download
+            .set_message(format!("Downloading {}", self.name));
+    }
+
+    fn download_progress(&self, downloaded: u64) {
+        self.download.set_position(downloaded);
+    }
+
+    fn download_retrying(&self, attempt: u32, max_attempts: u32, delay: Duration) {
+        self.download.set_message(format!(
+            "Retrying {} ({attempt}/{max_attempts}) in {delay:?}",
+            self.name
+        ));
+    }
+
+    fn download_finished(&self, path: &Path) {
+        let dir = path.parent().unwrap_or(path);
+        self.download
+            .set_style(ProgressStyle::with_template("{msg:.green} {bytes}").unwrap());
+        self.download
+            .finish_with_message(format!("✓ [{}] Downloaded to {dir:?}.", self.name));
+    }
+
+    fn cached(&self, path: &Path) {
+        let name = path.file_name().unwrap_or_default();
+        self.download
+            .set_style(ProgressStyle::with_template("{msg:.green}").unwrap());
+        self.download
+            .finish_with_message(format!("✓ [{}] Using cached {name:?}.", self.name));
+    }
+
+    fn verifying(&self, path: &Path) {
+        let name = path.file_name().unwrap_or_default();
+        self.extract
+            .set_message(format!("Verifying signature of {name:?}..."));
+    }
+
+    fn extracting(&self, path: &Path, outpath: &Path) {
+        self.extract
+            .set_message(format!("Extracting {path:?} into {outpath:?}..."));
+    }
+
+    fn extracted(&self, path: &Path) {
+        let name = path.file_name().unwrap_or_default();
+        self.extract
+            .set_style(ProgressStyle::with_template("{msg:.green}").unwrap());
+        self.extract
+            .finish_with_message(format!("✓ [{}] Extracted {name:?}.", self.name));
+    }
+
+    fn running_hooks(&self, stage: &str) {
+        self.install
+            .set_message(format!("Running {stage} hooks..."));
+    }
+
+    fn installed(&self, version: &ToolVersion, path: &Path) {
+        self.install
+            .set_style(ProgressStyle::with_template("{msg:.green}").unwrap());
+        self.install.finish_with_message(format!(
+            "✓ [{}] Updated to version {version} in {path:?}.",
+            self.name
+        ));
+    }
+
+    fn up_to_date(&self, version: &ToolVersion) {
+        self.m.remove(&self.extract);
+        self.m.remove(&self.install);
+        self.download
+            .set_style(ProgressStyle::with_template("{msg:.green}").unwrap());
+        self.download
+            .finish_with_message(format!("✓ [{}] is up to date: {version}", self.name));
+    }
+
+    fn warning(&self, message: &str) {
+        self.m.println(format!("⚠ [{}] {message}", self.name)).ok();
     }
 }
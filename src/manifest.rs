@@ -0,0 +1,147 @@
+use crate::domain::{ToolVersion, VersionError};
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tokio::fs::{read_to_string, write};
+
+const MANIFEST_FILE: &str = "manifest.toml";
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ManifestError {
+    #[error("Failed to read {path:?}.")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to write to {path:?}.")]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to deserialize {path:?}.")]
+    Deserialize {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("Failed to serialize manifest.")]
+    Serialize(#[from] toml::ser::Error),
+}
+
+/// Record of the binaries installed by dl-releases, stored in the data path
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Installed tools by repository name (user/repo_name)
+    #[serde(default)]
+    pub tools: BTreeMap<String, InstalledTool>,
+    /// Tools replaced by the last install of each repository, like the binaries
+    /// backed up by the flat layout
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub backups: BTreeMap<String, InstalledTool>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstalledTool {
+    /// Installed version
+    pub version: String,
+    /// Release tag the binary was installed from
+    pub tag: String,
+    /// Name of the downloaded asset
+    pub asset: String,
+    pub installed_at: Timestamp,
+}
+
+impl InstalledTool {
+    pub fn version(&self) -> Result<ToolVersion, VersionError> {
+        ToolVersion::from_str(&self.version)
+    }
+}
+
+impl Manifest {
+    /// Reads the manifest from `data_path`, an empty one is returned if it doesn't exist
+    pub async fn load(data_path: impl AsRef<Path>) -> Result<Self, ManifestError> {
+        let path = data_path.as_ref().join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let s = match read_to_string(&path).await {
+            Ok(s) => s,
+            Err(source) => return Err(ManifestError::Read { path, source }),
+        };
+        toml::from_str(&s).map_err(|source| ManifestError::Deserialize { path, source })
+    }
+
+    pub async fn save(&self, data_path: impl AsRef<Path>) -> Result<(), ManifestError> {
+        let path = data_path.as_ref().join(MANIFEST_FILE);
+        let s = toml::to_string_pretty(self)?;
+        write(&path, s)
+            .await
+            .map_err(|source| ManifestError::Write { path, source })
+    }
+
+    pub fn get(&self, repo: &str) -> Option<&InstalledTool> {
+        self.tools.get(repo)
+    }
+
+    /// Records `tool`, the replaced one becomes the backup of `repo`
+    pub fn insert(&mut self, repo: String, tool: InstalledTool) {
+        if let Some(previous) = self.tools.insert(repo.clone(), tool) {
+            self.backups.insert(repo, previous);
+        }
+    }
+
+    /// Swaps the tool of `repo` with its backup, like rolling back its binary, and
+    /// returns the restored one
+    pub fn rollback(&mut self, repo: &str) -> Option<&InstalledTool> {
+        let backup = self.backups.remove(repo)?;
+        if let Some(current) = self.tools.insert(repo.to_string(), backup) {
+            self.backups.insert(repo.to_string(), current);
+        }
+        self.tools.get(repo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use googletest::prelude::*;
+    use tempfile::tempdir;
+
+    #[gtest]
+    #[tokio::test]
+    async fn manifest_roundtrip_works() {
+        let data_path = tempdir().unwrap();
+        let mut manifest = Manifest::load(&data_path).await.unwrap();
+        expect_that!(manifest.tools, is_empty());
+        let tool = InstalledTool {
+            version: "0.54.1".to_string(),
+            tag: "v0.54.1".to_string(),
+            asset: "lazygit_0.54.1_linux_x86_64.tar.gz".to_string(),
+            installed_at: Timestamp::UNIX_EPOCH,
+        };
+        manifest.insert("jesseduffield/lazygit".to_string(), tool.clone());
+        manifest.save(&data_path).await.unwrap();
+        let mut manifest = Manifest::load(&data_path).await.unwrap();
+        expect_that!(manifest.get("jesseduffield/lazygit"), some(eq(&tool)));
+        expect_that!(manifest.rollback("jesseduffield/lazygit"), none());
+
+        let update = InstalledTool {
+            version: "0.55.0".to_string(),
+            tag: "v0.55.0".to_string(),
+            ..tool.clone()
+        };
+        manifest.insert("jesseduffield/lazygit".to_string(), update.clone());
+        manifest.save(&data_path).await.unwrap();
+        let mut manifest = Manifest::load(&data_path).await.unwrap();
+        expect_that!(manifest.rollback("jesseduffield/lazygit"), some(eq(&tool)));
+        // Rolling back twice returns to the update
+        expect_that!(
+            manifest.rollback("jesseduffield/lazygit"),
+            some(eq(&update))
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use crate::domain::{Asset, ToolVersion};
+use std::{path::Path, time::Duration};
+
+/// Receives the progress of a repository update, one reporter is used per repository
+pub trait Reporter: Send + Sync {
+    fn download_started(&self, asset: &Asset);
+    /// `downloaded` is the total number of bytes downloaded so far
+    fn download_progress(&self, downloaded: u64);
+    fn download_retrying(&self, attempt: u32, max_attempts: u32, delay: Duration);
+    fn download_finished(&self, path: &Path);
+    /// A verified copy of the asset was found in the cache, nothing was downloaded
+    fn cached(&self, path: &Path);
+    fn verifying(&self, path: &Path);
+    fn extracting(&self, path: &Path, outpath: &Path);
+    fn extracted(&self, path: &Path);
+    /// `stage` is the name of the hooks, eg: `pre_install`
+    fn running_hooks(&self, stage: &str);
+    fn installed(&self, version: &ToolVersion, path: &Path);
+    /// The installed version is already the wanted one
+    fn up_to_date(&self, version: &ToolVersion);
+    /// Problem that doesn't stop the update, eg: a missing checksum
+    fn warning(&self, message: &str);
+}
+
+/// Reporter ignoring every event
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopReporter;
+
+impl Reporter for NoopReporter {
+    fn download_started(&self, _asset: &Asset) {}
+    fn download_progress(&self, _downloaded: u64) {}
+    fn download_retrying(&self, _attempt: u32, _max_attempts: u32, _delay: Duration) {}
+    fn download_finished(&self, _path: &Path) {}
+    fn cached(&self, _path: &Path) {}
+    fn verifying(&self, _path: &Path) {}
+    fn extracting(&self, _path: &Path, _outpath: &Path) {}
+    fn extracted(&self, _path: &Path) {}
+    fn running_hooks(&self, _stage: &str) {}
+    fn installed(&self, _version: &ToolVersion, _path: &Path) {}
+    fn up_to_date(&self, _version: &ToolVersion) {}
+    fn warning(&self, _message: &str) {}
+}
+
+/// Reporter writing every event to the [`log`] facade, prefixed with `name`
+#[derive(Clone, Debug)]
+pub struct LogReporter {
+    name: String,
+}
+
+impl LogReporter {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into() }
+    }
+}
+
+impl Reporter for LogReporter {
+    fn download_started(&self, asset: &Asset) {
+        log::info!(
+            "[{}] Downloading {:?} ({} bytes).",
+            self.name,
+            asset.name,
+            asset.size
+        );
+    }
+
+    fn download_progress(&self, downloaded: u64) {
+        log::trace!("[{}] Downloaded {downloaded} bytes.", self.name);
+    }
+
+    fn download_retrying(&self, attempt: u32, max_attempts: u32, delay: Duration) {
+        log::warn!(
+            "[{}] Retrying download ({attempt}/{max_attempts}) in {delay:?}.",
+            self.name
+        );
+    }
+
+    fn download_finished(&self, path: &Path) {
+        log::info!("[{}] Downloaded to {path:?}.", self.name);
+    }
+
+    fn cached(&self, path: &Path) {
+        log::info!("[{}] Using cached download {path:?}.", self.name);
+    }
+
+    fn verifying(&self, path: &Path) {
+        log::info!("[{}] Verifying signature of {path:?}.", self.name);
+    }
+
+    fn extracting(&self, path: &Path, outpath: &Path) {
+        log::info!("[{}] Extracting {path:?} into {outpath:?}.", self.name);
+    }
+
+    fn extracted(&self, path: &Path) {
+        log::info!("[{}] Extracted {path:?}.", self.name);
+    }
+
+    fn running_hooks(&self, stage: &str) {
+        log::info!("[{}] Running {stage} hooks.", self.name);
+    }
+
+    fn installed(&self, version: &ToolVersion, path: &Path) {
+        log::info!("[{}] Installed version {version} in {path:?}.", self.name);
+    }
+
+    fn up_to_date(&self, version: &ToolVersion) {
+        log::info!("[{}] Up to date: {version}.", self.name);
+    }
+
+    fn warning(&self, message: &str) {
+        log::warn!("[{}] {message}", self.name);
+    }
+}
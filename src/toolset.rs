@@ -0,0 +1,276 @@
+use crate::{config::RepoConfig, domain::Repository, manifest::Manifest};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+use tokio::fs::read_to_string;
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ToolSetError {
+    #[error("Failed to read {path:?}.")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to deserialize {path:?}.")]
+    Deserialize {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("Failed to deserialize {path:?}.")]
+    DeserializeJson {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("Failed to serialize tool set.")]
+    Serialize(#[from] toml::ser::Error),
+    #[error("Failed to serialize tool set.")]
+    SerializeJson(#[from] serde_json::Error),
+    #[error("Failed to compare the config of {0}.")]
+    Compare(String, #[source] toml::ser::Error),
+}
+
+/// Installed tools shared with `export` and added to a config with `import`
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ToolSet {
+    #[serde(default)]
+    pub tools: Vec<ExportedTool>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExportedTool {
+    /// Config of the repository, pinned to the installed release
+    #[serde(flatten)]
+    pub config: RepoConfig,
+    /// Installed version
+    pub version: String,
+}
+
+/// Result of merging a [`ToolSet`] into the repositories of a config
+#[derive(Clone, Debug, Default)]
+pub struct Merge {
+    /// Repositories missing from the config
+    pub added: Vec<RepoConfig>,
+    /// Repositories already in the config with the same settings
+    pub unchanged: Vec<String>,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// A repository of a [`ToolSet`] configured differently in the config, the config
+/// is kept as is
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conflict {
+    pub repo: String,
+    /// Differing keys with the config value and the imported one
+    pub differences: Vec<(String, String, String)>,
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} is configured differently:", self.repo)?;
+        for (key, config, imported) in &self.differences {
+            write!(f, "\n  {key}: {config} (config) != {imported} (imported)")?;
+        }
+        Ok(())
+    }
+}
+
+impl ToolSet {
+    /// Tools of `manifest`, with the config of `repos` when they are in it. Repos
+    /// installed without being added to the config match their exact asset name.
+    /// Hooks aren't exported, as they run arbitrary commands.
+    pub fn from_installed(manifest: &Manifest, repos: &[(Repository, RepoConfig)]) -> Self {
+        let tools = manifest
+            .tools
+            .iter()
+            .map(|(repo, tool)| {
+                let config = repos.iter().find(|(o, _)| &o.to_string() == repo);
+                let mut config = match config {
+                    Some((_, o)) => o.clone(),
+                    None => RepoConfig::new(repo.clone(), tool.asset.to_lowercase()),
+                };
+                config.pre_install.clear();
+                config.post_install.clear();
+                // Tools installed from a file have no tag to pin
+                if !tool.tag.is_empty() {
+                    config.pin = Some(tool.tag.clone());
+                }
+                ExportedTool {
+                    config,
+                    version: tool.version.clone(),
+                }
+            })
+            .collect();
+        Self { tools }
+    }
+
+    /// Reads a tool set written by [`ToolSet::to_json`] if `path` ends in `.json`, or
+    /// by [`ToolSet::to_toml`] otherwise
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self, ToolSetError> {
+        let path = path.as_ref().to_owned();
+        let s = match read_to_string(&path).await {
+            Ok(s) => s,
+            Err(source) => return Err(ToolSetError::Read { path, source }),
+        };
+        if path.extension().is_some_and(|o| o == "json") {
+            serde_json::from_str(&s)
+                .map_err(|source| ToolSetError::DeserializeJson { path, source })
+        } else {
+            toml::from_str(&s).map_err(|source| ToolSetError::Deserialize { path, source })
+        }
+    }
+
+    pub fn to_toml(&self) -> Result<String, ToolSetError> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    pub fn to_json(&self) -> Result<String, ToolSetError> {
+        Ok(format!("{}\n", serde_json::to_string_pretty(self)?))
+    }
+
+    /// Splits the tools into the ones missing from `repos`, the ones already in it
+    /// and the ones configured differently. Exported tools are always pinned, so the
+    /// pin only conflicts with repositories pinned to another release.
+    pub fn merge(&self, repos: &[(Repository, RepoConfig)]) -> Result<Merge, ToolSetError> {
+        let mut merge = Merge::default();
+        for ExportedTool { config, .. } in &self.tools {
+            let existing = repos.iter().find(|(o, _)| o.to_string() == config.repo);
+            let Some((_, existing)) = existing else {
+                merge.added.push(config.clone());
+                continue;
+            };
+            let to_table = |o: &RepoConfig| {
+                toml::Table::try_from(o)
+                    .map_err(|source| ToolSetError::Compare(config.repo.clone(), source))
+            };
+            let follows_latest = existing.pin.is_none();
+            let existing = to_table(existing)?;
+            let imported = to_table(config)?;
+            let mut keys = existing.keys().chain(imported.keys()).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+            let differences = keys
+                .into_iter()
+                // Hooks aren't exported
+                .filter(|o| !["pre_install", "post_install"].contains(&o.as_str()))
+                .filter(|o| !(follows_latest && o.as_str() == "pin"))
+                .filter_map(|key| {
+                    let a = existing.get(key);
+                    let b = imported.get(key);
+                    (a != b).then(|| (key.clone(), show_value(a), show_value(b)))
+                })
+                .collect::<Vec<_>>();
+            if differences.is_empty() {
+                merge.unchanged.push(config.repo.clone());
+            } else {
+                merge.conflicts.push(Conflict {
+                    repo: config.repo.clone(),
+                    differences,
+                });
+            }
+        }
+        Ok(merge)
+    }
+}
+
+fn show_value(value: Option<&toml::Value>) -> String {
+    value.map_or("(none)".to_string(), ToString::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::InstalledTool;
+    use googletest::prelude::*;
+    use jiff::Timestamp;
+    use std::str::FromStr;
+    use tempfile::tempdir;
+
+    #[gtest]
+    #[tokio::test]
+    async fn export_and_merge_work() {
+        let mut manifest = Manifest::default();
+        for (repo, asset) in [
+            (
+                "jesseduffield/lazygit",
+                "lazygit_0.54.1_Linux_x86_64.tar.gz",
+            ),
+            ("sharkdp/fd", "fd-v10.2.0-x86_64-unknown-linux-gnu.tar.gz"),
+        ] {
+            let tool = InstalledTool {
+                version: "0.54.1".to_string(),
+                tag: "v0.54.1".to_string(),
+                asset: asset.to_string(),
+                installed_at: Timestamp::UNIX_EPOCH,
+            };
+            manifest.insert(repo.to_string(), tool);
+        }
+        let mut lazygit = RepoConfig::new(
+            "jesseduffield/lazygit".to_string(),
+            "linux_x86_64".to_string(),
+        );
+        lazygit.post_install = vec!["lazygit --help".to_string()];
+        let repos = vec![(Repository::from_str(&lazygit.repo).unwrap(), lazygit)];
+        let toolset = ToolSet::from_installed(&manifest, &repos);
+
+        let dir = tempdir().unwrap();
+        let toml_path = dir.path().join("tools.toml");
+        let json_path = dir.path().join("tools.json");
+        std::fs::write(&toml_path, toolset.to_toml().unwrap()).unwrap();
+        std::fs::write(&json_path, toolset.to_json().unwrap()).unwrap();
+        let from_json = ToolSet::load(&json_path).await.unwrap();
+        let toolset = ToolSet::load(&toml_path).await.unwrap();
+
+        expect_that!(from_json.tools, len(eq(2)));
+        expect_that!(
+            toolset.tools,
+            elements_are![
+                pat!(ExportedTool {
+                    config: pat!(RepoConfig {
+                        pat: eq("linux_x86_64"),
+                        pin: some(eq("v0.54.1")),
+                        post_install: is_empty(),
+                        ..
+                    }),
+                    version: eq("0.54.1"),
+                }),
+                pat!(ExportedTool {
+                    config: pat!(RepoConfig {
+                        pat: eq("fd-v10.2.0-x86_64-unknown-linux-gnu.tar.gz"),
+                        ..
+                    }),
+                    ..
+                }),
+            ]
+        );
+        let merge = toolset.merge(&repos).unwrap();
+        expect_that!(
+            merge.added,
+            elements_are![field!(RepoConfig.repo, eq("sharkdp/fd"))]
+        );
+        // Unpinned repos follow the latest release, which includes the exported one
+        expect_that!(merge.unchanged, elements_are![eq("jesseduffield/lazygit")]);
+        expect_that!(merge.conflicts, is_empty());
+
+        let mut lazygit = repos[0].1.clone();
+        lazygit.pat = "x86_64".to_string();
+        lazygit.pin = Some("v0.50.0".to_string());
+        let repos = vec![(repos[0].0.clone(), lazygit)];
+        let merge = toolset.merge(&repos).unwrap();
+        let difference = |key: &str, config: &str, imported: &str| {
+            (key.to_string(), config.to_string(), imported.to_string())
+        };
+        expect_that!(
+            merge.conflicts,
+            elements_are![pat!(Conflict {
+                repo: eq("jesseduffield/lazygit"),
+                differences: elements_are![
+                    eq(&difference("pat", "\"x86_64\"", "\"linux_x86_64\"")),
+                    eq(&difference("pin", "\"v0.50.0\"", "\"v0.54.1\"")),
+                ],
+            })]
+        );
+    }
+}
@@ -1,41 +1,180 @@
-use anyhow::Context;
+use crate::{
+    domain::{SignatureConfig, SignatureError, ToolVersion, VersionError, VersionScheme},
+    reporter::Reporter,
+};
 use flate2::read::GzDecoder;
-use indicatif::ProgressBar;
 use regex::Regex;
-use semver::Version;
 use std::{
-    ffi::OsStr,
+    env,
     fs::{self, File},
     io::{BufWriter, Write},
     os::unix::fs::PermissionsExt,
     path::{Path, PathBuf},
-    sync::LazyLock,
+    process::{ExitStatus, Stdio},
+    string::FromUtf8Error,
+    time::Duration,
 };
 use tar::Archive;
 use tempfile::NamedTempFile;
-use tokio::process::Command;
+use tokio::{process::Command, task::JoinError, time::timeout};
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum GetVersionError {
+    #[error("Binary not found: {0:?}.")]
+    BinaryNotFound(PathBuf),
+    #[error("Failed to execute {path:?}.")]
+    Spawn {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Timed out after {timeout:?} running {path:?}.")]
+    Timeout { path: PathBuf, timeout: Duration },
+    #[error("{path:?} exited with {status}.")]
+    Failed { path: PathBuf, status: ExitStatus },
+    #[error("Failed to read output.")]
+    InvalidOutput(#[from] FromUtf8Error),
+    #[error("No version found on: {0:?}.")]
+    NoMatch(String),
+    #[error(transparent)]
+    Version(#[from] VersionError),
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ExtractError {
+    #[error("File extension not supported: {0:?}.")]
+    UnsupportedExtension(PathBuf),
+    #[error("Failed to read {path:?}.")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to write {path:?}.")]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("{fname:?} not found in {path:?}.")]
+    NotFound { fname: PathBuf, path: PathBuf },
+    #[error("Failed to execute tokio task.")]
+    Task(#[from] JoinError),
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum VerifyError {
+    #[error("Failed to read {path:?}.")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error(transparent)]
+    Signature(#[from] SignatureError),
+    #[error("Failed to execute tokio task.")]
+    Task(#[from] JoinError),
+}
+
+/// How to query the version of a binary
+#[derive(Clone, Debug)]
+pub struct VersionProbe {
+    /// Arguments passed to the binary (eg: ["--version"])
+    pub args: Vec<String>,
+    /// Regex to find the version on the output, the first capture group is used if
+    /// present, otherwise the whole match
+    pub regex: Option<Regex>,
+    /// Versioning scheme used to parse the version
+    pub scheme: VersionScheme,
+    /// Maximum time the binary is allowed to run
+    pub timeout: Duration,
+}
+
+impl Default for VersionProbe {
+    fn default() -> Self {
+        Self {
+            args: vec!["--version".to_string()],
+            regex: None,
+            scheme: VersionScheme::default(),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Finds `name` on the directories of the `PATH` environment variable, paths
+/// containing a separator are returned as they are if they exist
+pub fn find_binary(name: impl AsRef<Path>) -> Option<PathBuf> {
+    let name = name.as_ref();
+    if name.components().count() > 1 {
+        return name.is_file().then(|| name.to_owned());
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|o| o.join(name))
+        .find(|o| {
+            o.metadata()
+                .is_ok_and(|o| o.is_file() && o.permissions().mode() & 0o111 != 0)
+        })
+}
+
+pub async fn get_version(path: impl AsRef<Path>) -> Result<ToolVersion, GetVersionError> {
+    get_version_with(path, &VersionProbe::default()).await
+}
 
-pub async fn get_version(path: impl AsRef<OsStr>) -> anyhow::Result<Version> {
-    let output = Command::new(path)
-        .arg("--version")
-        .output()
+/// Runs the binary in `path` to get its version. The binary runs with a null stdin, a
+/// cleared environment and inside a temporary directory, and it's killed if it takes
+/// longer than `probe.timeout`.
+pub async fn get_version_with(
+    path: impl AsRef<Path>,
+    probe: &VersionProbe,
+) -> Result<ToolVersion, GetVersionError> {
+    let path = path.as_ref();
+    let path = find_binary(path).ok_or_else(|| GetVersionError::BinaryNotFound(path.to_owned()))?;
+    let spawn_error = |source| GetVersionError::Spawn {
+        path: path.clone(),
+        source,
+    };
+    let workdir = tempfile::tempdir().map_err(spawn_error)?;
+    let output = Command::new(&path)
+        .args(&probe.args)
+        .stdin(Stdio::null())
+        .env_clear()
+        .current_dir(workdir.path())
+        .kill_on_drop(true)
+        .output();
+    let output = timeout(probe.timeout, output)
         .await
-        .context("Failed to execute command.")?;
+        .map_err(|_| GetVersionError::Timeout {
+            path: path.clone(),
+            timeout: probe.timeout,
+        })?
+        .map_err(spawn_error)?;
     if !output.status.success() {
-        anyhow::bail!("Failed to execute command.");
+        return Err(GetVersionError::Failed {
+            path,
+            status: output.status,
+        });
+    }
+    let s = String::from_utf8(output.stdout)?;
+    match &probe.regex {
+        Some(re) => extract_version_with(&s, re, probe.scheme),
+        None => extract_version(&s, probe.scheme),
     }
-    let s = String::from_utf8(output.stdout).context("Failed to read output.")?;
-    extract_version(&s)
 }
 
-pub fn extract_version(s: &str) -> anyhow::Result<Version> {
-    static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(\d+\.\d+\.\d+)").unwrap());
-    let version = RE
+pub fn extract_version(s: &str, scheme: VersionScheme) -> Result<ToolVersion, GetVersionError> {
+    Ok(scheme.extract(s)?)
+}
+
+pub fn extract_version_with(
+    s: &str,
+    re: &Regex,
+    scheme: VersionScheme,
+) -> Result<ToolVersion, GetVersionError> {
+    let version = re
         .captures(s)
-        .and_then(|o| o.get(1))
-        .with_context(|| format!("No version found on: {s:?}."))?
+        .and_then(|o| o.get(1).or_else(|| o.get(0)))
+        .ok_or_else(|| GetVersionError::NoMatch(s.to_string()))?
         .as_str();
-    Version::parse(version).context("Failed to parse version")
+    Ok(scheme.extract(version)?)
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -47,19 +186,18 @@ enum SupportedExtension {
 }
 
 impl SupportedExtension {
-    fn from_path(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+    fn from_path(path: &Path) -> Result<Self, ExtractError> {
         let extension = path
-            .as_ref()
             .file_name()
             .and_then(|x| x.to_str())
-            .context("Failed to get file_name.")?
+            .unwrap_or_default()
             .to_lowercase();
         if extension.ends_with(".tar.gz") {
             Ok(Self::TarGz)
         } else if extension.ends_with(".gz") {
             Ok(Self::Gz)
         } else {
-            anyhow::bail!("File extension not supported.")
+            Err(ExtractError::UnsupportedExtension(path.to_owned()))
         }
     }
 }
@@ -68,64 +206,98 @@ pub fn extract_file(
     path: impl AsRef<Path>,
     fname: impl AsRef<Path>,
     outpath: impl AsRef<Path>,
-) -> anyhow::Result<PathBuf> {
+) -> Result<PathBuf, ExtractError> {
     let path = path.as_ref();
     let fname = fname.as_ref();
     let outpath = outpath.as_ref().join(fname);
     let extension = SupportedExtension::from_path(path)?;
-    let file = File::open(path).context("Failed to open file.")?;
+    let read_error = |source| ExtractError::Read {
+        path: path.to_owned(),
+        source,
+    };
+    let write_error = |source| ExtractError::Write {
+        path: outpath.clone(),
+        source,
+    };
+    let file = File::open(path).map_err(read_error)?;
     match extension {
         SupportedExtension::Gz => {
             let mut decoder = GzDecoder::new(file);
-            let tmp_file = NamedTempFile::new().context("Failed to create tmp file.")?;
+            // Create the tmp file in the same directory so it can be renamed into place
+            let parent = outpath.parent().unwrap_or(Path::new(""));
+            let tmp_file = NamedTempFile::new_in(parent).map_err(write_error)?;
             let tmp_path = tmp_file.path().to_owned();
             let mut writer = BufWriter::new(tmp_file);
-            std::io::copy(&mut decoder, &mut writer)?;
-            writer.flush()?;
-            set_execute_permission(&tmp_path)?;
-            fs::rename(&tmp_path, &outpath).context("Failed to move file.")?;
+            std::io::copy(&mut decoder, &mut writer).map_err(read_error)?;
+            writer.flush().map_err(write_error)?;
+            set_execute_permission(&tmp_path).map_err(write_error)?;
+            fs::rename(&tmp_path, &outpath).map_err(write_error)?;
             Ok(outpath)
         }
         SupportedExtension::TarGz => {
             let mut archive = Archive::new(GzDecoder::new(file));
-            for entry in archive.entries().context("Failed to read entries.")? {
-                let mut entry = entry.context("Failed to read entry.")?;
-                let path = entry.path()?;
+            for entry in archive.entries().map_err(read_error)? {
+                let mut entry = entry.map_err(read_error)?;
+                let path = entry.path().map_err(read_error)?;
                 let Some(fname_) = path.file_name() else {
                     continue;
                 };
                 if fname_ == fname {
-                    entry.unpack(&outpath)?;
-                    set_execute_permission(&outpath)?;
+                    entry.unpack(&outpath).map_err(write_error)?;
+                    set_execute_permission(&outpath).map_err(write_error)?;
                     return Ok(outpath);
                 }
             }
-            anyhow::bail!("{fname:?} not found in {path:?}.");
+            Err(ExtractError::NotFound {
+                fname: fname.to_owned(),
+                path: path.to_owned(),
+            })
         }
     }
 }
 
-fn set_execute_permission(path: impl AsRef<Path>) -> anyhow::Result<()> {
+fn set_execute_permission(path: impl AsRef<Path>) -> std::io::Result<()> {
     let mut perms = fs::metadata(&path)?.permissions();
     perms.set_mode(perms.mode() | 0o111);
-    fs::set_permissions(&path, perms)?;
-    Ok(())
+    fs::set_permissions(&path, perms)
 }
 
 pub async fn extract_file_async(
     path: impl AsRef<Path>,
     fname: &str,
     outpath: impl AsRef<Path>,
-    pb: &ProgressBar,
-) -> anyhow::Result<PathBuf> {
+    reporter: &dyn Reporter,
+) -> Result<PathBuf, ExtractError> {
     let path = path.as_ref().to_owned();
     let fname = fname.to_owned();
     let outpath = outpath.as_ref().to_owned();
-    pb.set_message(format!("Extracting {path:?} into {outpath:?}..."));
-    let outpath = tokio::task::spawn_blocking(move || extract_file(path, fname, outpath))
-        .await
-        .context("Failed to execute tokio task.")??;
-    Ok(outpath)
+    reporter.extracting(&path, &outpath);
+    let extracted =
+        tokio::task::spawn_blocking(move || extract_file(path, fname, outpath)).await??;
+    reporter.extracted(&extracted);
+    Ok(extracted)
+}
+
+/// Verifies the signature of the file in `path` without blocking the runtime
+pub async fn verify_signature_async(
+    path: impl AsRef<Path>,
+    config: &SignatureConfig,
+    signature: String,
+    reporter: &dyn Reporter,
+) -> Result<(), VerifyError> {
+    let path = path.as_ref().to_owned();
+    reporter.verifying(&path);
+    let config = config.clone();
+    tokio::task::spawn_blocking(move || {
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(source) => return Err(VerifyError::Read { path, source }),
+        };
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        config.verify(&name, &data, &signature)?;
+        Ok(())
+    })
+    .await?
 }
 
 #[cfg(test)]
@@ -143,12 +315,54 @@ mod tests {
             ("rust-analyzer", "0.3.2555"),
         ] {
             let s = read_to_string(format!("src/test_files/{o}_example.txt")).unwrap();
-            let version = extract_version(&s);
-            let expected = Version::parse(expected).unwrap();
-            expect_that!(version, ok(eq(&expected)), "Failed for {o}");
+            let version = extract_version(&s, VersionScheme::Semver).map(|o| o.to_string());
+            expect_that!(version, ok(eq(expected)), "Failed for {o}");
         }
     }
 
+    #[gtest]
+    fn extract_version_with_works() {
+        let s = "tool 1.4.2 (built with go1.22.5 and llvm 17.0.6)";
+        let scheme = VersionScheme::Semver;
+        let extract = |re: &str| {
+            let re = Regex::new(re).unwrap();
+            extract_version_with(s, &re, scheme).map(|o| o.to_string())
+        };
+        expect_that!(extract(r"tool (\S+)"), ok(eq("1.4.2")));
+        expect_that!(extract(r"\d+\.\d+\.\d+\b"), ok(eq("1.4.2")));
+        expect_that!(extract(r"llvm ([\d.]+)"), ok(eq("17.0.6")));
+    }
+
+    #[gtest]
+    #[tokio::test]
+    async fn get_version_sandbox_works() {
+        let dir = tempdir().unwrap();
+        let script = |name: &str, content: &str| {
+            let path = dir.path().join(name);
+            fs::write(&path, format!("#!/bin/sh\n{content}\n")).unwrap();
+            set_execute_permission(&path).unwrap();
+            path
+        };
+        let probe = VersionProbe {
+            timeout: Duration::from_millis(500),
+            ..Default::default()
+        };
+        let version = script("version", "echo tool 1.2.3");
+        let res = get_version_with(&version, &probe)
+            .await
+            .map(|o| o.to_string());
+        expect_that!(res, ok(eq("1.2.3")));
+        // Waits on stdin, which is null so it finishes right away
+        let stdin = script("stdin", "read x; echo tool 1.2.$x${HOME}0");
+        let res = get_version_with(&stdin, &probe)
+            .await
+            .map(|o| o.to_string());
+        expect_that!(res, ok(eq("1.2.0")));
+        let hang = script("hang", "exec sleep 10");
+        let res = get_version_with(&hang, &probe).await;
+        expect_that!(res, err(displays_as(starts_with("Timed out"))));
+    }
+
     #[gtest]
     fn extract_file_works() {
         for fname in ["test_file.tar.gz", "test_file.gz"] {
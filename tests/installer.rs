@@ -0,0 +1,644 @@
+//! End-to-end installs against a local server serving the recorded releases of
+//! `src/domain/test_files` and fixture archives
+
+use dl_releases::{
+    cache::Cache,
+    config::RepoConfig,
+    domain::{ChecksumPolicy, Repository},
+    github_client::{Capture, ClientOptions, GithubClient, GithubError},
+    install::InstallLayout,
+    installer::{Installer, InstallerError, Outcome, Update},
+    lockfile::{LockedRelease, Lockfile},
+    manifest::{InstalledTool, Manifest},
+};
+use flate2::{Compression, write::GzEncoder};
+use googletest::prelude::*;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+use tempfile::TempDir;
+use wiremock::{
+    Mock, MockServer, ResponseTemplate,
+    matchers::{header, method, path},
+};
+
+const REPO: &str = "fixtures/dl-releases-fixture";
+const BINARY: &str = "dl-releases-fixture";
+const RELEASE_VERSION: &str = "0.54.1";
+const TAG: &str = "v0.54.1";
+const ASSET: &str = "lazygit_0.54.1_linux_x86_64.tar.gz";
+
+struct Harness {
+    server: MockServer,
+    dir: TempDir,
+}
+
+impl Harness {
+    async fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("data")).unwrap();
+        fs::create_dir(dir.path().join("bin")).unwrap();
+        Self {
+            server: MockServer::start().await,
+            dir,
+        }
+    }
+
+    fn download_dir(&self) -> PathBuf {
+        self.dir.path().join("data")
+    }
+
+    fn binaries_dir(&self) -> PathBuf {
+        self.dir.path().join("bin")
+    }
+
+    fn binary(&self) -> PathBuf {
+        self.binaries_dir().join(BINARY)
+    }
+
+    fn installer(&self) -> Installer {
+        let client =
+            GithubClient::new(&self.server.uri(), None, &ClientOptions::default()).unwrap();
+        Installer::builder(client, self.download_dir(), self.binaries_dir()).build()
+    }
+
+    fn installer_with(&self, capture: Capture) -> Installer {
+        let client = GithubClient::new(&self.server.uri(), None, &ClientOptions::default())
+            .unwrap()
+            .with_capture(capture);
+        Installer::builder(client, self.download_dir(), self.binaries_dir()).build()
+    }
+
+    fn asset_url(&self, name: &str) -> String {
+        format!("{}/download/{name}", self.server.uri())
+    }
+
+    /// Serves the recorded lazygit release as the latest release of [`REPO`] and by
+    /// its tag, with its download urls pointing to this server and [`ASSET`] replaced
+    /// by `archive`
+    async fn mount_release(&self, archive: &[u8]) {
+        self.mount_release_json(self.release(archive)).await;
+    }
+
+    /// Recorded lazygit release served by [`Harness::mount_release`]
+    fn release(&self, archive: &[u8]) -> Value {
+        let s = fs::read_to_string("src/domain/test_files/jesseduffield_lazygit.json").unwrap();
+        let mut release = serde_json::from_str::<Value>(&s).unwrap();
+        for asset in release["assets"].as_array_mut().unwrap() {
+            let name = asset["name"].as_str().unwrap().to_owned();
+            asset["browser_download_url"] = self.asset_url(&name).into();
+            if name == ASSET {
+                asset["size"] = archive.len().into();
+                asset["digest"] = format!("sha256:{}", hex::encode(Sha256::digest(archive))).into();
+            }
+        }
+        release
+    }
+
+    async fn mount_release_json(&self, release: Value) {
+        Mock::given(method("GET"))
+            .and(path(format!("/repos/{REPO}/releases/tags/{TAG}")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&release))
+            .mount(&self.server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/repos/{REPO}/releases/latest")))
+            .respond_with(ResponseTemplate::new(200).set_body_json(release))
+            .mount(&self.server)
+            .await;
+    }
+
+    async fn mount_asset(&self, archive: Vec<u8>) {
+        Mock::given(method("GET"))
+            .and(path(format!("/download/{ASSET}")))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(archive))
+            .mount(&self.server)
+            .await;
+    }
+}
+
+fn repo() -> Repository {
+    Repository::from_str(REPO).unwrap()
+}
+
+fn repo_config(trust_release_version: bool) -> RepoConfig {
+    let mut config = RepoConfig::new(REPO.to_string(), "linux_x86_64".to_string());
+    config.trust_release_version = trust_release_version;
+    config
+}
+
+/// Script printing `version` like `lazygit --version`
+fn script(version: &str) -> String {
+    format!("#!/bin/sh\necho \"commit=abc, build source=binaryRelease, version={version}\"\n")
+}
+
+/// Archive with a [`BINARY`] script printing `version`
+fn archive(version: &str) -> Vec<u8> {
+    let script = script(version);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(script.len() as u64);
+    header.set_mode(0o755);
+    header.set_cksum();
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    builder
+        .append_data(&mut header, BINARY, script.as_bytes())
+        .unwrap();
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+fn run(path: &Path) -> String {
+    let output = Command::new(path).output().unwrap();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[gtest]
+#[tokio::test]
+async fn install_and_update_work() {
+    let harness = Harness::new().await;
+    let archive = archive(RELEASE_VERSION);
+    harness.mount_release(&archive).await;
+    harness.mount_asset(archive).await;
+    // An older version installed before
+    fs::write(harness.binary(), script("0.50.0")).unwrap();
+    fs::set_permissions(harness.binary(), fs::Permissions::from_mode(0o755)).unwrap();
+
+    let outcome = harness
+        .installer()
+        .install(&repo(), &repo_config(false))
+        .await;
+
+    let outcome = outcome.unwrap();
+    let update = outcome.update().unwrap();
+    expect_that!(update.path, eq(&harness.binary()));
+    expect_that!(update.tool.version, eq(RELEASE_VERSION));
+    expect_that!(update.tool.asset, eq(ASSET));
+    expect_that!(run(&harness.binary()), contains_substring(RELEASE_VERSION));
+    let backup = harness.download_dir().join("backups").join(BINARY);
+    expect_that!(run(&backup), contains_substring("0.50.0"));
+    let manifest = Manifest::load(harness.download_dir()).await.unwrap();
+    expect_that!(
+        manifest.get(REPO).map(|o| o.version.as_str()),
+        some(eq(RELEASE_VERSION))
+    );
+}
+
+#[gtest]
+#[tokio::test]
+async fn up_to_date_works() {
+    let harness = Harness::new().await;
+    let archive = archive(RELEASE_VERSION);
+    harness.mount_release(&archive).await;
+    Mock::given(method("GET"))
+        .and(path(format!("/download/{ASSET}")))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(archive))
+        .expect(1)
+        .mount(&harness.server)
+        .await;
+    let installer = harness.installer();
+    // The installed version is read from the manifest written by the first install
+    let config = repo_config(true);
+
+    let first = installer.install(&repo(), &config).await.unwrap();
+    let second = installer.install(&repo(), &config).await.unwrap();
+
+    expect_that!(first, pat!(Outcome::Installed(_)));
+    expect_that!(
+        second,
+        pat!(Outcome::UpToDate(displays_as(eq(RELEASE_VERSION))))
+    );
+}
+
+#[gtest]
+#[tokio::test]
+async fn version_mismatch_fails() {
+    let harness = Harness::new().await;
+    let archive = archive("0.53.0");
+    harness.mount_release(&archive).await;
+    harness.mount_asset(archive).await;
+
+    let outcome = harness
+        .installer()
+        .install(&repo(), &repo_config(false))
+        .await;
+
+    expect_that!(
+        outcome,
+        err(pat!(InstallerError::VersionMismatch {
+            expected: displays_as(eq(RELEASE_VERSION)),
+            extracted: displays_as(eq("0.53.0")),
+        }))
+    );
+    expect_false!(harness.binary().exists());
+}
+
+#[gtest]
+#[tokio::test]
+async fn not_found_fails() {
+    let harness = Harness::new().await;
+    let installer = harness.installer();
+
+    let missing_release = installer.install(&repo(), &repo_config(false)).await;
+    harness.mount_release(&archive(RELEASE_VERSION)).await;
+    let missing_asset = installer.install(&repo(), &repo_config(false)).await;
+
+    expect_that!(missing_release, err(pat!(InstallerError::LatestRelease(_))));
+    expect_that!(
+        missing_asset,
+        err(pat!(InstallerError::Download(
+            eq(ASSET),
+            pat!(GithubError::Status {
+                status: eq(&404),
+                ..
+            })
+        )))
+    );
+    expect_false!(harness.binary().exists());
+}
+
+#[gtest]
+#[tokio::test]
+async fn missing_checksum_file_warns() {
+    let harness = Harness::new().await;
+    let archive = archive(RELEASE_VERSION);
+    let mut release = harness.release(&archive);
+    // Only `checksums.txt`, which isn't served, has the checksum
+    for asset in release["assets"].as_array_mut().unwrap() {
+        asset["digest"] = Value::Null;
+    }
+    harness.mount_release_json(release).await;
+    harness.mount_asset(archive).await;
+    let mut config = repo_config(false);
+    config.checksum = Some(ChecksumPolicy::Require);
+
+    let required = harness.installer().install(&repo(), &config).await;
+    config.checksum = Some(ChecksumPolicy::Warn);
+    let warned = harness.installer().install(&repo(), &config).await;
+
+    expect_that!(required, err(pat!(InstallerError::FindChecksum(_))));
+    expect_that!(warned, ok(pat!(Outcome::Installed(_))));
+}
+
+#[gtest]
+#[tokio::test]
+async fn truncated_download_resumes() {
+    let harness = Harness::new().await;
+    let archive = archive(RELEASE_VERSION);
+    harness.mount_release(&archive).await;
+    let half = archive.len() / 2;
+    Mock::given(method("GET"))
+        .and(path(format!("/download/{ASSET}")))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(&archive[..half]))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&harness.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("/download/{ASSET}")))
+        .and(header("range", format!("bytes={half}-")))
+        .respond_with(ResponseTemplate::new(206).set_body_bytes(&archive[half..]))
+        .expect(1)
+        .mount(&harness.server)
+        .await;
+
+    let outcome = harness
+        .installer()
+        .install(&repo(), &repo_config(false))
+        .await;
+
+    expect_that!(outcome, ok(pat!(Outcome::Installed(_))));
+    expect_that!(run(&harness.binary()), contains_substring(RELEASE_VERSION));
+    let entries = Cache::new(harness.download_dir()).list().unwrap();
+    let downloaded = entries.first().map(|o| fs::read(o.path()).unwrap());
+    expect_that!(downloaded, some(eq(&archive)));
+}
+
+#[gtest]
+#[tokio::test]
+async fn reinstall_uses_cache() {
+    let harness = Harness::new().await;
+    let archive = archive(RELEASE_VERSION);
+    harness.mount_release(&archive).await;
+    Mock::given(method("GET"))
+        .and(path(format!("/download/{ASSET}")))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(archive))
+        .expect(1)
+        .mount(&harness.server)
+        .await;
+    let installer = harness.installer();
+    // The binary isn't in PATH, so it's installed again
+    let config = repo_config(false);
+
+    let first = installer.install(&repo(), &config).await.unwrap();
+    fs::remove_file(harness.binary()).unwrap();
+    let second = installer.install(&repo(), &config).await.unwrap();
+
+    fs::remove_file(harness.binary()).unwrap();
+    let client = GithubClient::new(&harness.server.uri(), None, &ClientOptions::default()).unwrap();
+    let third = Installer::builder(client, harness.download_dir(), harness.binaries_dir())
+        .keep_downloads(false)
+        .build()
+        .install(&repo(), &config)
+        .await
+        .unwrap();
+
+    expect_that!(first, pat!(Outcome::Installed(_)));
+    expect_that!(second, pat!(Outcome::Installed(_)));
+    expect_that!(third, pat!(Outcome::Installed(_)));
+    expect_that!(run(&harness.binary()), contains_substring(RELEASE_VERSION));
+    // Not downloading assets to keep doesn't remove the ones already cached
+    let entries = Cache::new(harness.download_dir()).list().unwrap();
+    expect_that!(entries, len(eq(1)));
+}
+
+#[gtest]
+#[tokio::test]
+async fn record_and_replay_work() {
+    let harness = Harness::new().await;
+    let archive = archive(RELEASE_VERSION);
+    harness.mount_release(&archive).await;
+    harness.mount_asset(archive).await;
+    let capture = harness.dir.path().join("capture");
+    harness
+        .installer_with(Capture::Record(capture.clone()))
+        .install(&repo(), &repo_config(false))
+        .await
+        .unwrap();
+    // Without any mock, so every request would fail
+    harness.server.reset().await;
+    let replay = Harness::new().await;
+    let client = GithubClient::new(&harness.server.uri(), None, &ClientOptions::default())
+        .unwrap()
+        .with_capture(Capture::Replay(capture));
+    let installer =
+        Installer::builder(client, replay.download_dir(), replay.binaries_dir()).build();
+
+    let outcome = installer.install(&repo(), &repo_config(false)).await;
+
+    expect_that!(outcome, ok(pat!(Outcome::Installed(_))));
+    expect_that!(run(&replay.binary()), contains_substring(RELEASE_VERSION));
+    expect_that!(harness.server.received_requests().await, some(is_empty()));
+}
+
+#[gtest]
+#[tokio::test]
+async fn replay_reproduces_errors() {
+    let harness = Harness::new().await;
+    let capture = harness.dir.path().join("capture");
+    let recorded = harness
+        .installer_with(Capture::Record(capture.clone()))
+        .install(&repo(), &repo_config(false))
+        .await;
+    harness.server.reset().await;
+
+    let replayed = harness
+        .installer_with(Capture::Replay(capture))
+        .install(&repo(), &repo_config(false))
+        .await;
+
+    let not_found = || {
+        err(pat!(InstallerError::LatestRelease(pat!(
+            GithubError::Status {
+                status: eq(&404),
+                ..
+            }
+        ))))
+    };
+    expect_that!(recorded, not_found());
+    expect_that!(replayed, not_found());
+    expect_that!(harness.server.received_requests().await, some(is_empty()));
+}
+
+#[gtest]
+#[tokio::test]
+async fn proxy_works() {
+    let harness = Harness::new().await;
+    let archive = archive(RELEASE_VERSION);
+    harness.mount_release(&archive).await;
+    harness.mount_asset(archive).await;
+    let options = ClientOptions {
+        proxy: Some(harness.server.uri()),
+        ..Default::default()
+    };
+    // The API is only reachable through the proxy
+    let client = GithubClient::new("http://api.github.invalid", None, &options).unwrap();
+    let installer =
+        Installer::builder(client, harness.download_dir(), harness.binaries_dir()).build();
+
+    let outcome = installer.install(&repo(), &repo_config(false)).await;
+
+    expect_that!(outcome, ok(pat!(Outcome::Installed(_))));
+    let requests = harness.server.received_requests().await.unwrap();
+    expect_that!(
+        requests
+            .first()
+            .map(|o| o.url.host_str().unwrap_or_default().to_owned()),
+        some(eq("api.github.invalid"))
+    );
+}
+
+#[gtest]
+#[tokio::test]
+async fn offline_install_works() {
+    let harness = Harness::new().await;
+    let archive = archive(RELEASE_VERSION);
+    let missing = harness
+        .installer()
+        .install(&repo(), &repo_config(false))
+        .await;
+    harness.mount_release(&archive).await;
+    harness.mount_asset(archive).await;
+    harness
+        .installer()
+        .install(&repo(), &repo_config(false))
+        .await
+        .unwrap();
+    fs::remove_file(harness.binary()).unwrap();
+    let requests = harness.server.received_requests().await.unwrap().len();
+    let client = GithubClient::new(&harness.server.uri(), None, &ClientOptions::default()).unwrap();
+    let installer = Installer::builder(client, harness.download_dir(), harness.binaries_dir())
+        .offline(true)
+        .build();
+
+    let outcome = installer.install(&repo(), &repo_config(false)).await;
+    let mut pinned = repo_config(false);
+    pinned.pin = Some("v0.50.0".to_string());
+    let missing_pin = installer.install(&repo(), &pinned).await;
+
+    expect_that!(missing, err(pat!(InstallerError::LatestRelease(_))));
+    expect_that!(outcome, ok(pat!(Outcome::Installed(_))));
+    expect_that!(run(&harness.binary()), contains_substring(RELEASE_VERSION));
+    expect_that!(missing_pin, err(pat!(InstallerError::NotCached(_))));
+    expect_that!(
+        harness.server.received_requests().await.map(|o| o.len()),
+        some(eq(requests))
+    );
+}
+
+#[gtest]
+#[tokio::test]
+async fn install_file_works() {
+    let harness = Harness::new().await;
+    let file = harness.dir.path().join(ASSET);
+    fs::write(&file, archive(RELEASE_VERSION)).unwrap();
+    let installer = harness.installer();
+
+    // Without release metadata the version is read from the binary
+    let update = installer
+        .install_file(&repo(), &repo_config(false), &file)
+        .await;
+    // With it, the archive is checked against the digest of the release asset
+    harness.mount_release(&archive(RELEASE_VERSION)).await;
+    installer.plan(&repo(), &repo_config(false)).await.unwrap();
+    fs::write(&file, archive("0.53.0")).unwrap();
+    let mismatch = installer
+        .install_file(&repo(), &repo_config(false), &file)
+        .await;
+
+    expect_that!(
+        update,
+        ok(pat!(Update {
+            tool: pat!(InstalledTool {
+                version: eq(RELEASE_VERSION),
+                tag: eq(""),
+                asset: eq(ASSET),
+                ..
+            }),
+            ..
+        }))
+    );
+    expect_that!(run(&harness.binary()), contains_substring(RELEASE_VERSION));
+    expect_that!(mismatch, err(pat!(InstallerError::Checksum(_))));
+    let manifest = Manifest::load(harness.download_dir()).await.unwrap();
+    expect_that!(
+        manifest.get(REPO).map(|o| o.version.as_str()),
+        some(eq(RELEASE_VERSION))
+    );
+}
+
+#[gtest]
+#[tokio::test]
+async fn locked_install_works() {
+    let harness = Harness::new().await;
+    let archive = archive(RELEASE_VERSION);
+    let sha256 = hex::encode(Sha256::digest(&archive));
+    harness.mount_release(&archive).await;
+    harness.mount_asset(archive).await;
+    let locked = harness
+        .installer()
+        .lock(&repo(), &repo_config(false))
+        .await
+        .unwrap();
+    let installer_with = |release: Option<&LockedRelease>| {
+        let mut lockfile = Lockfile::default();
+        if let Some(release) = release {
+            lockfile.insert(REPO.to_string(), release.clone());
+        }
+        let client =
+            GithubClient::new(&harness.server.uri(), None, &ClientOptions::default()).unwrap();
+        Installer::builder(client, harness.download_dir(), harness.binaries_dir())
+            .lockfile(lockfile)
+            .build()
+    };
+
+    let tampered = LockedRelease {
+        sha256: Some("0".repeat(64)),
+        ..locked.clone()
+    };
+    let mismatch = installer_with(Some(&tampered))
+        .install(&repo(), &repo_config(false))
+        .await;
+    let outcome = installer_with(Some(&locked))
+        .install(&repo(), &repo_config(false))
+        .await;
+    let not_locked = installer_with(None)
+        .install(&repo(), &repo_config(false))
+        .await;
+
+    expect_that!(
+        locked,
+        pat!(LockedRelease {
+            tag: eq(TAG),
+            asset: eq(ASSET),
+            url: eq(&harness.asset_url(ASSET)),
+            sha256: some(eq(&sha256)),
+        })
+    );
+    expect_that!(mismatch, err(pat!(InstallerError::Checksum(_))));
+    expect_that!(
+        outcome,
+        ok(pat!(Outcome::Installed(pat!(Update {
+            tool: pat!(InstalledTool { tag: eq(TAG), .. }),
+            ..
+        }))))
+    );
+    expect_that!(not_locked, err(pat!(InstallerError::NotLocked(eq(REPO)))));
+}
+
+#[gtest]
+#[tokio::test]
+async fn failed_post_install_removes_new_binary() {
+    let harness = Harness::new().await;
+    let archive = archive(RELEASE_VERSION);
+    harness.mount_release(&archive).await;
+    harness.mount_asset(archive).await;
+    // Installed before, but outside the binaries dir, so there's no backup
+    let mut manifest = Manifest::default();
+    manifest.insert(
+        REPO.to_string(),
+        InstalledTool {
+            version: "0.50.0".to_string(),
+            tag: "v0.50.0".to_string(),
+            asset: ASSET.to_string(),
+            installed_at: jiff::Timestamp::UNIX_EPOCH,
+        },
+    );
+    manifest.save(harness.download_dir()).await.unwrap();
+    let mut config = repo_config(true);
+    config.post_install = vec!["false".to_string()];
+    config.rollback_on_hook_failure = true;
+
+    let outcome = harness.installer().install(&repo(), &config).await;
+
+    expect_that!(
+        outcome,
+        err(pat!(InstallerError::PostInstallHook {
+            rollback: none(),
+            ..
+        }))
+    );
+    expect_false!(harness.binary().exists());
+}
+
+#[gtest]
+#[tokio::test]
+async fn failed_post_install_restores_flat_binary() {
+    let harness = Harness::new().await;
+    let archive = archive(RELEASE_VERSION);
+    harness.mount_release(&archive).await;
+    harness.mount_asset(archive).await;
+    // Installed with the flat layout before switching to the versioned one
+    fs::write(harness.binary(), script("0.50.0")).unwrap();
+    fs::set_permissions(harness.binary(), fs::Permissions::from_mode(0o755)).unwrap();
+    let mut config = repo_config(true);
+    config.layout = InstallLayout::Versioned;
+    config.post_install = vec!["false".to_string()];
+    config.rollback_on_hook_failure = true;
+
+    let outcome = harness.installer().install(&repo(), &config).await;
+
+    expect_that!(
+        outcome,
+        err(pat!(InstallerError::PostInstallHook {
+            rollback: none(),
+            ..
+        }))
+    );
+    expect_false!(harness.binary().is_symlink());
+    expect_that!(run(&harness.binary()), contains_substring("0.50.0"));
+}